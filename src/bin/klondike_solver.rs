@@ -4,20 +4,53 @@ use anyhow::Error;
 #[allow(dead_code)]
 mod klondike;
 
+#[cfg(not(feature = "xoshiro_shuffle"))]
+use crate::klondike::WINABLE_SEEDS;
 use crate::klondike::{
-    ActiveCardIterator, Card, CardPlayIterator, Play, Rank, Source, Stack, StackId, Table,
+    ActiveCardIterator, CanonicalKey, Card, CardPlayIterator, NavigationOrder, Play, Rank, Source,
+    Stack, StackId, Table, UndoToken, CANONICAL_KEY_LEN,
 };
 use argh::FromArgs;
 use core::iter::Iterator;
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::{
     cmp::Ordering,
-    collections::HashSet,
-    fs::File,
-    io::{stdin, stdout, Write},
+    collections::{HashMap, HashSet},
+    convert::TryInto,
+    fs::{self, File},
+    io::{stdin, stdout, Read, Write},
+    path::{Path, PathBuf},
+    time::Instant,
 };
 
-#[derive(Debug, Clone, Copy)]
+const MAX_ITERATIONS: usize = 5_000_000;
+
+// Scores and priorities the search uses to order plays; higher is tried first.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Serialize)]
+struct Weights {
+    foundation: isize,
+    waste: isize,
+    waste_king: isize,
+    waste_king_buried_queen: isize,
+    waste_king_no_queen: isize,
+    from_foundation: isize,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            foundation: 5,
+            waste: 5,
+            waste_king: 1,
+            waste_king_buried_queen: -1,
+            waste_king_no_queen: 99,
+            from_foundation: -10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 struct WeightedPlay {
     play: Play,
     score: isize,
@@ -50,13 +83,13 @@ impl Ord for WeightedPlay {
 }
 
 impl WeightedPlay {
-    pub fn new(play: Play, table: &Table) -> Self {
+    pub fn new(play: Play, table: &Table, weights: &Weights) -> Self {
         let (score, priority) = match play {
             Play::MoveCards(source, target) => match target {
                 StackId::Foundation1
                 | StackId::Foundation2
                 | StackId::Foundation3
-                | StackId::Foundation4 => (5, 0),
+                | StackId::Foundation4 => (weights.foundation, 0),
                 StackId::Tableau1
                 | StackId::Tableau2
                 | StackId::Tableau3
@@ -66,10 +99,10 @@ impl WeightedPlay {
                 | StackId::Tableau7 => match source.stack {
                     StackId::Waste => {
                         let stack = table.get_stack(source.stack);
-                        let card = stack.get_card(source.index).expect("get_card");
-                        let score = 5;
+                        let card = stack.top_card().expect("top_card");
+                        let score = weights.waste;
                         let priority = if card.rank == Rank::King {
-                            Self::waste_king_priority(source, target, stack, card, table)
+                            Self::waste_king_priority(source, target, stack, card, table, weights)
                         } else {
                             1
                         };
@@ -85,7 +118,7 @@ impl WeightedPlay {
                     StackId::Foundation1
                     | StackId::Foundation2
                     | StackId::Foundation3
-                    | StackId::Foundation4 => (-10, 0),
+                    | StackId::Foundation4 => (weights.from_foundation, 0),
                     _ => (0, 0),
                 },
                 _ => (0, 0),
@@ -102,12 +135,10 @@ impl WeightedPlay {
     fn tableau_move(source: Source, _target: StackId, table: &Table) -> (isize, isize) {
         let stack = table.get_stack(source.stack);
         let score = 0;
-        if stack.is_top_face_up_card(source.index) {
-            if stack.len() > 0 {
-                (score, source.index as isize + 1)
-            } else {
-                (score, 1)
-            }
+        // Taking every face up card uncovers whatever is under them.
+        let face_down = stack.len() - stack.face_up_cards().count();
+        if source.index == face_down {
+            (score, source.index as isize + 1)
         } else {
             (score, 1)
         }
@@ -119,6 +150,7 @@ impl WeightedPlay {
         _stack: &Stack,
         card: &Card,
         table: &Table,
+        weights: &Weights,
     ) -> isize {
         if let Some(queen_card_location) = table.find_card(Rank::Queen, card.suit) {
             match queen_card_location.stack {
@@ -130,17 +162,19 @@ impl WeightedPlay {
                 | StackId::Tableau6
                 | StackId::Tableau7 => {
                     let stack = table.get_stack(queen_card_location.stack);
-                    let card = stack.get_card(queen_card_location.index).expect("get_card");
-                    if card.face_up {
-                        1
+                    let queen_showing = stack
+                        .face_up_cards()
+                        .any(|queen| queen.rank == Rank::Queen && queen.suit == card.suit);
+                    if queen_showing {
+                        weights.waste_king
                     } else {
-                        -1
+                        weights.waste_king_buried_queen
                     }
                 }
-                _ => 1,
+                _ => weights.waste_king,
             }
         } else {
-            99
+            weights.waste_king_no_queen
         }
     }
 }
@@ -152,6 +186,23 @@ enum PlayIteratorPhase<'a> {
     Done,
 }
 
+fn king_can_fill_column(table: &Table) -> bool {
+    let on_waste = table
+        .waste
+        .get_top_card()
+        .is_some_and(|card| card.rank == Rank::King);
+    on_waste
+        || table.tableaux.iter().any(|tableau| {
+            // A king with nothing under it already fills its column.
+            let buried = tableau.face_up_cards().count() < tableau.len();
+            buried
+                && tableau
+                    .face_up_cards()
+                    .next()
+                    .is_some_and(|card| card.rank == Rank::King)
+        })
+}
+
 struct PlayIterator<'a> {
     table: &'a Table,
     phase: PlayIteratorPhase<'a>,
@@ -176,12 +227,14 @@ impl<'a> Iterator for PlayIterator<'a> {
                     self.phase = PlayIteratorPhase::Stock;
                 }
                 PlayIteratorPhase::Stock => {
-                    self.phase =
-                        PlayIteratorPhase::ActiveCards(ActiveCardIterator::new(self.table), None);
+                    self.phase = PlayIteratorPhase::ActiveCards(
+                        ActiveCardIterator::new(self.table, NavigationOrder::Standard),
+                        None,
+                    );
                     if self.table.has_cards_in_stock() {
                         return Some(Play::DrawFromStock);
                     }
-                    if self.table.has_cards_in_waste() {
+                    if self.table.can_recycle() {
                         return Some(Play::RecycleWaste);
                     }
                 }
@@ -196,10 +249,7 @@ impl<'a> Iterator for PlayIterator<'a> {
                     } else {
                         let next_active_card = iterator.next();
                         if let Some(active_card) = next_active_card {
-                            let stack = self.table.get_stack(active_card.stack);
-                            let card = stack.get_card(active_card.index).expect("get_card");
-                            let card_play_iterator =
-                                CardPlayIterator::new(self.table, card, active_card);
+                            let card_play_iterator = CardPlayIterator::new(self.table, active_card);
                             *card_iterator = Some(card_play_iterator);
                         } else {
                             self.phase = PlayIteratorPhase::Done;
@@ -215,30 +265,37 @@ impl<'a> Iterator for PlayIterator<'a> {
     }
 }
 
+// The table a node stands for isn't kept; the search applies each node's
+// play to one shared table and takes it back when the node is popped.
+#[derive(Deserialize, Serialize)]
 struct SearchNode {
     parent: Option<usize>,
     index: usize,
     play: Play,
-    table: Table,
     weighted_plays: Vec<WeightedPlay>,
 }
 
 impl SearchNode {
-    fn new(parent: Option<usize>, index: usize, play: Play, table: Table) -> SearchNode {
-        let mut weighted_plays: Vec<WeightedPlay> = PlayIterator::new(&table)
-            .map(|play| WeightedPlay::new(play, &table))
+    fn new(
+        parent: Option<usize>,
+        index: usize,
+        play: Play,
+        table: &Table,
+        weights: &Weights,
+    ) -> SearchNode {
+        let mut weighted_plays: Vec<WeightedPlay> = PlayIterator::new(table)
+            .map(|play| WeightedPlay::new(play, table, weights))
             .collect();
         weighted_plays.sort();
         Self {
             parent,
             index,
             play,
-            table,
             weighted_plays,
         }
     }
 
-    fn filter_play(&self, play: &Play, previous_plays: &Vec<Play>) -> Option<Play> {
+    fn filter_play(&self, play: &Play, table: &Table, previous_plays: &Vec<Play>) -> Option<Play> {
         match play {
             Play::RecycleWaste => {
                 if previous_plays.len() > 0 {
@@ -269,10 +326,14 @@ impl SearchNode {
                     | StackId::Foundation4 => None,
                     StackId::Waste => Some(*play),
                     _ => {
-                        let stack = self.table.get_stack(source.stack);
+                        let stack = table.get_stack(source.stack);
                         if source.index == 0 {
                             if stack.get_card(0).expect("get_card").rank == Rank::King {
                                 return None;
+                            } else if stack.len() == 1 && !king_can_fill_column(table) {
+                                // Emptying the column gains nothing until a
+                                // king can move into it.
+                                None
                             } else {
                                 Some(*play)
                             }
@@ -288,51 +349,165 @@ impl SearchNode {
         }
     }
 
-    fn search(
+    // The best play left to try from table, which must be this node's.
+    fn next_play(
         &mut self,
-        next_index: usize,
+        table: &Table,
         previous_plays: &Vec<Play>,
         stepping: bool,
-    ) -> Option<SearchNode> {
+    ) -> Option<Play> {
         while let Some(weighted_play) = self.weighted_plays.pop() {
             if stepping {
                 println!("chose {:?}", weighted_play);
             }
-            let table = self.table.clone();
-            if let Some(play) = self.filter_play(&weighted_play.play, previous_plays) {
-                let new_table = make_move(play, &table);
-                return Some(Self::new(
-                    Some(self.index),
-                    next_index,
-                    weighted_play.play,
-                    new_table,
-                ));
+            if let Some(play) = self.filter_play(&weighted_play.play, table, previous_plays) {
+                return Some(play);
             }
         }
         None
     }
 }
 
-fn test_plays_iter(table: Table, verbose: bool, start_stepping: bool) -> Option<Vec<Play>> {
+// Everything needed to pick a search up again where it left off.
+#[derive(Deserialize, Serialize)]
+struct Search {
+    seed: u64,
+    weights: Weights,
+    // As dealt; replaying the nodes' plays on it gives the current table.
+    table: Table,
+    search_nodes: Vec<SearchNode>,
+    iterations: usize,
+    max_foundation: usize,
+    // Positions already searched, keyed so that tables equivalent but for
+    // the cursor count as one. Saved as raw keys after the rest.
+    #[serde(skip)]
+    seen: HashSet<CanonicalKey>,
+}
+
+impl Search {
+    fn new(seed: u64, table: Table, weights: Weights) -> Self {
+        Self {
+            seed,
+            weights,
+            search_nodes: vec![SearchNode::new(None, 0, Play::Setup, &table, &weights)],
+            table,
+            iterations: 0,
+            max_foundation: 0,
+            seen: HashSet::new(),
+        }
+    }
+
+    // A checkpoint is the search as one line of JSON, then every seen key
+    // back to back.
+    fn load(path: &Path) -> Result<Self, Error> {
+        let bytes = fs::read(path).map_err(Error::msg)?;
+        let end = bytes
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .ok_or_else(|| Error::msg("checkpoint has no search"))?;
+        let mut search: Search = serde_json::from_slice(&bytes[..end]).map_err(Error::msg)?;
+        let keys = &bytes[end + 1..];
+        if keys.len() % CANONICAL_KEY_LEN != 0 {
+            return Err(Error::msg("checkpoint ends partway through a key"));
+        }
+        search.seen = keys
+            .chunks_exact(CANONICAL_KEY_LEN)
+            .map(|key| key.try_into().expect("key"))
+            .collect();
+        Ok(search)
+    }
+
+    // Written beside the last checkpoint and renamed over it, so a search
+    // stopped partway through a save can still resume from the one before.
+    fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut bytes = serde_json::to_vec(self).map_err(Error::msg)?;
+        bytes.push(b'\n');
+        bytes.reserve(self.seen.len() * CANONICAL_KEY_LEN);
+        for key in &self.seen {
+            bytes.extend_from_slice(key);
+        }
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        fs::write(&temp_path, bytes).map_err(Error::msg)?;
+        fs::rename(&temp_path, path).map_err(Error::msg)
+    }
+}
+
+struct SearchStats {
+    started: Instant,
+    nodes_expanded: usize,
+    max_depth: usize,
+    duplicates: usize,
+}
+
+impl SearchStats {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            nodes_expanded: 0,
+            max_depth: 0,
+            duplicates: 0,
+        }
+    }
+
+    fn expanded(&mut self, depth: usize) {
+        self.nodes_expanded += 1;
+        self.max_depth = self.max_depth.max(depth);
+    }
+
+    fn pruned(&mut self) {
+        self.duplicates += 1;
+    }
+
+    fn print(&self, depth: usize) {
+        let seconds = self.started.elapsed().as_secs_f64();
+        println!(
+            "nodes {} depth {} max depth {} duplicates pruned {} ({:.0} nodes/s)",
+            self.nodes_expanded,
+            depth,
+            self.max_depth,
+            self.duplicates,
+            self.nodes_expanded as f64 / seconds.max(f64::EPSILON)
+        );
+    }
+}
+
+fn test_plays_iter(
+    mut search: Search,
+    verbose: bool,
+    start_stepping: bool,
+    checkpoint: Option<(usize, &Path)>,
+) -> Option<Vec<Play>> {
     let mut stepping = start_stepping;
-    let mut max_foundation = 0;
-    let mut search_nodes = Vec::new();
-    let mut tables: HashSet<Table> = HashSet::new();
-    search_nodes.push(SearchNode::new(None, 0, Play::Setup, table));
-    let mut iterations = 0;
-    while search_nodes.len() > 0 {
-        let len = search_nodes.len();
+    // The one table the search walks, with a play applied for every node
+    // past the first and taken back as that node is popped.
+    let mut table = search.table.clone();
+    let mut undo_tokens: Vec<UndoToken> = search
+        .search_nodes
+        .iter()
+        .skip(1)
+        .map(|node| apply(&mut table, node.play))
+        .collect();
+    search.seen.insert(table.canonical_key());
+    // Only gathered when someone will see them.
+    let mut stats = if verbose {
+        Some(SearchStats::new())
+    } else {
+        None
+    };
+    while search.search_nodes.len() > 0 {
+        let len = search.search_nodes.len();
         let last_index = len - 1;
         let mut traverse = last_index;
         let mut parents = Vec::new();
-        while let Some(parent) = search_nodes[traverse].parent {
+        while let Some(parent) = search.search_nodes[traverse].parent {
             parents.push(parent);
             traverse = parent;
         }
         parents.reverse();
         let mut plays: Vec<Play> = parents
             .iter()
-            .map(|parent| search_nodes[*parent].play)
+            .map(|parent| search.search_nodes[*parent].play)
             .collect();
         if stepping {
             let mut s = String::new();
@@ -342,104 +517,222 @@ fn test_plays_iter(table: Table, verbose: bool, start_stepping: bool) -> Option<
             match s.trim() {
                 "c" => stepping = false,
                 "p" => {
+                    // Prints in the form --from-stdin reads back.
                     println!("plays: {:?}", plays);
-                    println!("table: {:#?}", search_nodes[last_index].table);
+                    println!("{}", table);
                 }
                 _ => (),
             }
-        } else if iterations % 1_000_000 == 1 {
+        } else if search.iterations % 1_000_000 == 1 {
             if verbose {
                 println!("plays: {:?}", plays);
-                println!("table: {:#?}", search_nodes[last_index].table);
+                println!("table: {:#?}", table);
+            }
+            if let Some(stats) = &stats {
+                stats.print(len);
             }
         }
-        let cards_in_foundation = search_nodes[last_index].table.cards_in_foundation();
-        if cards_in_foundation > max_foundation {
-            max_foundation = cards_in_foundation;
+        let cards_in_foundation = table.cards_in_foundation();
+        if cards_in_foundation > search.max_foundation {
+            search.max_foundation = cards_in_foundation;
             if verbose {
-                println!("new max foundation {}", max_foundation);
+                println!("new max foundation {}", search.max_foundation);
                 println!("plays: {:?}", plays);
-                println!("table: {:#?}", search_nodes[last_index].table);
+                println!("table: {:#?}", table);
             }
         }
-        if let Some(node) = search_nodes[last_index].search(len, &plays, stepping) {
-            if node.table.winner() {
-                plays.push(node.play);
-                if verbose {
-                    println!("Winner! {:#?}", node.table);
-                    println!("plays: {:?} final {:?}", plays, node.play);
+        let next = search.search_nodes[last_index].next_play(&table, &plays, stepping);
+        if let Some(play) = next {
+            let token = apply(&mut table, play);
+            if !search.seen.insert(table.canonical_key()) {
+                // Everything from a position already seen has been, or is
+                // being, searched from there, so it isn't expanded again.
+                table.unapply(token);
+                if let Some(stats) = &mut stats {
+                    stats.pruned();
                 }
-                return Some(plays);
-            }
-            if stepping {
-                if verbose {
-                    println!("{:#?}", node.table);
-                    println!("{:#?}", node.weighted_plays);
+            } else {
+                undo_tokens.push(token);
+                let node = SearchNode::new(Some(last_index), len, play, &table, &search.weights);
+                if table.winner() {
+                    plays.push(node.play);
+                    if verbose {
+                        println!("Winner! {:#?}", table);
+                        println!("plays: {:?} final {:?}", plays, node.play);
+                    }
+                    if let Some(stats) = &stats {
+                        stats.print(len + 1);
+                    }
+                    return Some(plays);
+                }
+                if stepping {
+                    if verbose {
+                        println!("{:#?}", table);
+                        println!("{:#?}", node.weighted_plays);
+                    }
+                }
+                if let Some(stats) = &mut stats {
+                    stats.expanded(len + 1);
                 }
+                search.search_nodes.push(node);
             }
-            tables.insert(node.table.clone());
-            search_nodes.push(node);
         } else {
-            search_nodes.pop();
+            search.search_nodes.pop();
+            if let Some(token) = undo_tokens.pop() {
+                table.unapply(token);
+            }
             if stepping {
-                let len = search_nodes.len();
+                let len = search.search_nodes.len();
                 if len > 0 {
                     let last_index = len - 1;
                     if verbose {
-                        println!("returning to {}", search_nodes.len() - 1);
-                        println!("table: {:#?}", search_nodes[last_index].table);
+                        println!("returning to {}", search.search_nodes.len() - 1);
+                        println!("table: {:#?}", table);
                         println!(
                             "weighted_plays: {:#?}",
-                            search_nodes[last_index].weighted_plays
+                            search.search_nodes[last_index].weighted_plays
                         );
                     }
                 }
             }
         }
-        iterations += 1;
-        if iterations > 5_000_000 {
+        search.iterations += 1;
+        if let Some((every, path)) = checkpoint {
+            if search.iterations.is_multiple_of(every) {
+                if let Err(error) = search.save(path) {
+                    println!("checkpoint failed: {}", error);
+                }
+            }
+        }
+        if search.iterations > MAX_ITERATIONS {
             if verbose {
                 println!("Iteration limit met");
                 println!("plays: {:?}", plays);
-                let len = search_nodes.len();
-                if len > 0 {
-                    let last_index = len - 1;
-                    println!("table: {:#?}", search_nodes[last_index].table);
-                }
+                println!("table: {:#?}", table);
             }
             break;
         }
     }
-    if search_nodes.len() == 0 {
+    if search.search_nodes.len() == 0 {
         if verbose {
             println!("exhaustive search failed to find win");
         }
     }
+    if let Some(stats) = &stats {
+        stats.print(search.search_nodes.len());
+    }
     None
 }
 
-fn make_move(play: Play, table: &Table) -> Table {
-    let mut new_table = table.clone();
-    match play {
-        Play::DrawFromStock => {
-            assert!(new_table.has_cards_in_stock());
-            new_table.deal_from_stock()
+enum Deepening {
+    Solved,
+    Cutoff(usize),
+    Stopped,
+}
+
+// Iterative-deepening A*, which finds a shortest win at the cost of
+// searching the shallow part of the tree over and over.
+struct OptimalSearch {
+    plays: Vec<Play>,
+    seen: HashMap<CanonicalKey, usize>,
+    iterations: usize,
+}
+
+impl OptimalSearch {
+    // Walks a single table, applying each play and taking it back again.
+    fn search(&mut self, table: &mut Table, bound: usize) -> Deepening {
+        let depth = self.plays.len();
+        // The fewest plays that could still win, since every card left has
+        // to go up to a foundation on its own.
+        let estimate = depth + table.cards_remaining_to_win();
+        if estimate > bound {
+            return Deepening::Cutoff(estimate);
         }
-        Play::RecycleWaste => {
-            assert!(!new_table.has_cards_in_stock());
-            new_table.recycle_waste();
+        if table.winner() {
+            return Deepening::Solved;
         }
-        Play::MoveCards(source, stack_id) => {
-            new_table.take_selected_cards_from_stack(source.stack, source.index);
-            new_table.put_hand_on_stack(source, stack_id);
+        self.iterations += 1;
+        if self.iterations > MAX_ITERATIONS {
+            return Deepening::Stopped;
+        }
+        let key = table.canonical_key();
+        if self.seen.get(&key).is_some_and(|seen| *seen <= depth) {
+            return Deepening::Cutoff(usize::MAX);
+        }
+        self.seen.insert(key, depth);
+
+        let mut next_bound = usize::MAX;
+        let plays: Vec<Play> = PlayIterator::new(table).collect();
+        for play in plays {
+            let token = match table.apply(play) {
+                Ok(token) => token,
+                Err(error) => panic!("{:?} failed: {:?}", play, error),
+            };
+            self.plays.push(play);
+            let result = self.search(table, bound);
+            table.unapply(token);
+            match result {
+                Deepening::Solved => return Deepening::Solved,
+                Deepening::Stopped => return Deepening::Stopped,
+                Deepening::Cutoff(estimate) => next_bound = next_bound.min(estimate),
+            }
+            self.plays.pop();
+        }
+        Deepening::Cutoff(next_bound)
+    }
+}
+
+fn optimal_plays(table: &Table, verbose: bool) -> Option<Vec<Play>> {
+    let mut search = OptimalSearch {
+        plays: Vec::new(),
+        seen: HashMap::new(),
+        iterations: 0,
+    };
+    let mut table = table.clone();
+    let mut bound = table.cards_remaining_to_win();
+    loop {
+        search.seen.clear();
+        match search.search(&mut table, bound) {
+            Deepening::Solved => {
+                println!("optimal solution is {} plays", search.plays.len());
+                return Some(search.plays);
+            }
+            Deepening::Cutoff(usize::MAX) => {
+                if verbose {
+                    println!("exhaustive search failed to find win");
+                }
+                return None;
+            }
+            Deepening::Cutoff(next_bound) => {
+                if verbose {
+                    println!("deepening to {} plays", next_bound);
+                }
+                bound = next_bound;
+            }
+            Deepening::Stopped => {
+                if verbose {
+                    println!("Iteration limit met at {} plays", bound);
+                }
+                return None;
+            }
         }
+    }
+}
+
+fn apply(table: &mut Table, play: Play) -> UndoToken {
+    match play {
+        Play::RecycleWaste => assert!(!table.has_cards_in_stock()),
         Play::Setup => panic!("Unhandled play"),
+        _ => (),
+    }
+    match table.apply(play) {
+        Ok(token) => token,
+        Err(error) => panic!("{:?} failed: {:?}", play, error),
     }
-    new_table
 }
 
 /// Options
-#[derive(FromArgs, Debug, Clone, Copy)]
+#[derive(FromArgs, Debug, Clone)]
 struct Opt {
     /// verbose
     #[argh(switch)]
@@ -456,34 +749,166 @@ struct Opt {
     /// step at start
     #[argh(switch)]
     start_stepping: bool,
+
+    /// save the search every N iterations so it can be resumed
+    #[argh(option)]
+    checkpoint_every: Option<usize>,
+
+    /// resume a search from a checkpoint file
+    #[argh(option)]
+    resume: Option<String>,
+
+    /// solve a board read from stdin, one line per stack
+    #[argh(switch)]
+    from_stdin: bool,
+
+    /// solve every seed in WINABLE_SEEDS and report any that fail
+    #[cfg(not(feature = "xoshiro_shuffle"))]
+    #[argh(switch)]
+    verify_winnable_list: bool,
+
+    /// find a shortest win instead of the first one; much slower
+    #[argh(switch)]
+    optimal: bool,
+
+    /// score for moving a card to a foundation
+    #[argh(option, default = "Weights::default().foundation")]
+    w_foundation: isize,
+
+    /// score for moving a card from the waste to a tableau
+    #[argh(option, default = "Weights::default().waste")]
+    w_waste: isize,
+
+    /// priority for moving a king from the waste when its queen is showing
+    #[argh(option, default = "Weights::default().waste_king")]
+    w_waste_king: isize,
+
+    /// priority for moving a king from the waste when its queen is face down
+    #[argh(option, default = "Weights::default().waste_king_buried_queen")]
+    w_waste_king_buried_queen: isize,
+
+    /// priority for moving a king from the waste when its queen can't be found
+    #[argh(option, default = "Weights::default().waste_king_no_queen")]
+    w_waste_king_no_queen: isize,
+
+    /// score for moving a card back down from a foundation
+    #[argh(option, default = "Weights::default().from_foundation")]
+    w_from_foundation: isize,
+}
+
+impl Opt {
+    fn weights(&self) -> Weights {
+        Weights {
+            foundation: self.w_foundation,
+            waste: self.w_waste,
+            waste_king: self.w_waste_king,
+            waste_king_buried_queen: self.w_waste_king_buried_queen,
+            waste_king_no_queen: self.w_waste_king_no_queen,
+            from_foundation: self.w_from_foundation,
+        }
+    }
+}
+
+// A resumed search goes on with the weights it was started with, so any
+// given on the command line have to be the same. Weights left at their
+// defaults count as not given.
+fn check_resumed_weights(saved: &Weights, requested: &Weights) -> Result<(), Error> {
+    if requested == saved || *requested == Weights::default() {
+        return Ok(());
+    }
+    Err(Error::msg(format!(
+        "checkpoint was searched with {:?}, not {:?}",
+        saved, requested
+    )))
+}
+
+fn solve(search: Search, opt: &Opt) -> Option<(u64, Vec<Play>)> {
+    let seed = search.seed;
+    if opt.optimal {
+        // Deepening starts over each time, so only the first board matters.
+        return optimal_plays(&search.table, opt.verbose).map(|plays| (seed, plays));
+    }
+    let path = PathBuf::from(format!("checkpoint_{}.json", seed));
+    let checkpoint = opt.checkpoint_every.map(|every| (every, path.as_path()));
+    test_plays_iter(search, opt.verbose, opt.start_stepping, checkpoint).map(|plays| (seed, plays))
+}
+
+// The seeds that fail to solve, in order. Each gets the usual iteration
+// cap, so a failure may only mean the search gave up.
+#[cfg(not(feature = "xoshiro_shuffle"))]
+fn unsolved_seeds(seeds: &[u64], opt: &Opt) -> Vec<u64> {
+    let weights = opt.weights();
+    seeds
+        .par_iter()
+        .copied()
+        .filter(|seed| solve(Search::new(*seed, Table::new(*seed), weights), opt).is_none())
+        .collect()
 }
 
 fn main() -> Result<(), Error> {
     let opt: Opt = argh::from_env();
+    let weights = opt.weights();
+    if opt.verbose && opt.resume.is_none() {
+        println!("weights {:?}", weights);
+    }
 
-    let wins: Vec<(u64, Vec<Play>)> = (opt.seed..opt.seed + opt.count)
-        .into_par_iter()
-        .filter_map(|seed| {
-            let table = Table::new(seed);
+    #[cfg(not(feature = "xoshiro_shuffle"))]
+    if opt.verify_winnable_list {
+        let failures = unsolved_seeds(WINABLE_SEEDS, &opt);
+        let total = WINABLE_SEEDS.len();
+        println!("{}/{} solved", total - failures.len(), total);
+        if failures.is_empty() {
+            return Ok(());
+        }
+        println!("failed: {:?}", failures);
+        return Err(Error::msg(format!(
+            "{} winnable seeds failed",
+            failures.len()
+        )));
+    }
 
-            println!("testing {}", seed);
+    let (first_seed, last_seed, wins): (u64, u64, Vec<(u64, Vec<Play>)>) =
+        if let Some(path) = &opt.resume {
+            let search = Search::load(Path::new(path))?;
+            check_resumed_weights(&search.weights, &weights)?;
+            let seed = search.seed;
+            println!("resuming {} at iteration {}", seed, search.iterations);
             if opt.verbose {
-                println!("table {:#?}", table);
+                println!("weights {:?}", search.weights);
             }
+            (seed, seed, solve(search, &opt).into_iter().collect())
+        } else if opt.from_stdin {
+            let mut text = String::new();
+            stdin().read_to_string(&mut text).map_err(Error::msg)?;
+            let table: Table = text.parse()?;
+            println!("solving board from stdin");
+            if opt.verbose {
+                println!("table:\n{}", table);
+            }
+            let search = Search::new(opt.seed, table, weights);
+            let wins = solve(search, &opt).into_iter().collect();
+            (opt.seed, opt.seed, wins)
+        } else {
+            let wins = (opt.seed..opt.seed + opt.count)
+                .into_par_iter()
+                .filter_map(|seed| {
+                    let table = Table::new(seed);
 
-            test_plays_iter(table, opt.verbose, opt.start_stepping)
-                .and_then(|plays| Some((seed, plays)))
-        })
-        .collect();
+                    println!("testing {}", seed);
+                    if opt.verbose {
+                        println!("table {:#?}", table);
+                    }
+
+                    solve(Search::new(seed, table, weights), &opt)
+                })
+                .collect();
+            (opt.seed, opt.seed + opt.count - 1, wins)
+        };
     let seeds: Vec<u64> = wins.iter().map(|(seed, _)| *seed).collect();
     println!("wins = {:?}", seeds);
     let winning_plays = serde_json::to_string(&wins).map_err(Error::msg)?;
-    let mut file = File::create(format!(
-        "winning_plays_{}_{}.json",
-        opt.seed,
-        opt.seed + opt.count - 1
-    ))
-    .map_err(Error::msg)?;
+    let mut file = File::create(format!("winning_plays_{}_{}.json", first_seed, last_seed))
+        .map_err(Error::msg)?;
     file.write_all(winning_plays.as_bytes())
         .map_err(Error::msg)?;
 
@@ -493,10 +918,26 @@ fn main() -> Result<(), Error> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::klondike::Suit;
+    #[cfg(not(feature = "xoshiro_shuffle"))]
+    use crate::klondike::is_known_winnable;
+    use crate::klondike::{
+        make_deck, make_deck_with, Difficulty, GameEvent, MoveError, ShuffleRng, Suit,
+        TableOptions, HINT_DEPTH, SUITS, TABLEAUX, TWO_SUITS,
+    };
+    use enum_iterator::IntoEnumIterator;
+    use rand::SeedableRng;
+    use std::{env, process, str::FromStr};
 
     const TEST_SEED: u64 = 324;
 
+    fn card(rank: Rank, suit: Suit, face_up: bool) -> Card {
+        Card {
+            suit,
+            rank,
+            face_up,
+        }
+    }
+
     #[test]
     fn test_recycle_waste() {
         let table = Table::new(TEST_SEED);
@@ -517,6 +958,246 @@ mod test {
             table.get_stack(StackId::Stock),
             work_table.get_stack(StackId::Stock)
         );
+
+        for count in [1, 3] {
+            let mut work_table = table.clone();
+            work_table.deal_specific(count);
+            work_table.deal_specific(count);
+            let dealt = work_table.clone();
+            work_table.recycle_waste();
+            assert_eq!(dealt, work_table);
+
+            while work_table.has_cards_in_stock() {
+                work_table.deal_specific(count);
+            }
+            work_table.recycle_waste();
+            assert_eq!(
+                table.get_stack(StackId::Stock),
+                work_table.get_stack(StackId::Stock)
+            );
+            assert!(!work_table.has_cards_in_waste());
+
+            while work_table.has_cards_in_stock() {
+                work_table.deal_specific(count);
+            }
+            work_table.deal_specific(count);
+            assert_eq!(
+                table.get_stack(StackId::Stock),
+                work_table.get_stack(StackId::Stock)
+            );
+        }
+    }
+
+    #[test]
+    fn test_deal_specific() {
+        let table = Table::new(TEST_SEED);
+        let stock_len = table.stock.len();
+        for count in [1, 3, stock_len + 5] {
+            let mut work_table = table.clone();
+            work_table.deal_specific(count);
+            let dealt = count.min(stock_len);
+            assert_eq!(work_table.waste.len(), dealt);
+            assert_eq!(work_table.stock.len(), stock_len - dealt);
+            assert_eq!(work_table.move_count(), 1);
+            assert!(work_table.waste.iter().all(|card| card.face_up));
+            let top = work_table.waste.get_top_card().expect("top");
+            let last_dealt = table.stock.get_card(stock_len - dealt).expect("card");
+            assert_eq!((top.rank, top.suit), (last_dealt.rank, last_dealt.suit));
+        }
+
+        let mut work_table = table.clone();
+        work_table.deal_specific(0);
+        assert_eq!(work_table, table);
+
+        work_table.deal_specific(stock_len);
+        work_table.deal_specific(3);
+        assert_eq!(work_table.stock, table.stock);
+        assert!(!work_table.has_cards_in_waste());
+        assert_eq!(work_table.move_count(), 2);
+        assert_eq!(work_table.last_event(), Some(GameEvent::Recycled));
+
+        work_table.deal_specific(3);
+        assert_eq!(work_table.waste.len(), 3);
+    }
+
+    #[test]
+    fn test_difficulty_presets() {
+        let preset = |difficulty| {
+            let options = TableOptions::from_difficulty(difficulty);
+            (
+                options.draw_count(),
+                options.redeal_limit,
+                options.auto_play,
+                options.scoring,
+            )
+        };
+        assert_eq!(preset(Difficulty::Easy), (1, None, true, false));
+        assert_eq!(preset(Difficulty::Standard), (3, None, false, false));
+        assert_eq!(preset(Difficulty::Vegas), (3, Some(1), false, true));
+        assert_eq!(
+            TableOptions::from_difficulty(Difficulty::default()),
+            TableOptions::default()
+        );
+
+        let mut table = Table::new(TEST_SEED);
+        assert_eq!(table.vegas_score(), -52);
+        table.options = TableOptions::from_difficulty(Difficulty::Easy);
+        table.deal_from_stock();
+        assert_eq!(table.waste.len(), 1);
+
+        table.options = TableOptions::from_difficulty(Difficulty::Vegas);
+        while table.has_cards_in_stock() {
+            table.deal_from_stock();
+        }
+        assert!(table.can_recycle());
+        table.apply_play(Play::RecycleWaste).expect("recycle");
+        while table.has_cards_in_stock() {
+            table.deal_from_stock();
+        }
+        assert!(!table.can_recycle());
+        let moves = table.move_count();
+        table.deal_from_stock();
+        assert_eq!(table.move_count(), moves);
+        assert_eq!(
+            table.apply_play(Play::RecycleWaste),
+            Err(MoveError::EmptyStack)
+        );
+    }
+
+    #[test]
+    fn test_max_movable_run() {
+        let tableau = |cards| {
+            let table = Table::from_layout(Vec::new(), Vec::new(), Vec::new(), vec![cards]);
+            table.tableaux[0].clone()
+        };
+        let run = tableau(vec![
+            card(Rank::Nine, Suit::Club, false),
+            card(Rank::Eight, Suit::Heart, true),
+            card(Rank::Seven, Suit::Spade, true),
+            card(Rank::Six, Suit::Diamond, true),
+            card(Rank::Five, Suit::Club, true),
+        ]);
+        assert_eq!(run.max_movable_run(1), 4);
+        assert_eq!(run.max_movable_run(3), 2);
+        assert_eq!(run.max_movable_run(4), 1);
+        assert_eq!(run.max_movable_run(0), 0);
+        assert_eq!(run.max_movable_run(5), 0);
+        assert!(run.is_valid_run(1));
+
+        let broken = tableau(vec![
+            card(Rank::Eight, Suit::Heart, true),
+            card(Rank::Seven, Suit::Spade, true),
+            card(Rank::Six, Suit::Spade, true),
+            card(Rank::Five, Suit::Heart, true),
+        ]);
+        assert_eq!(broken.max_movable_run(0), 2);
+        assert_eq!(broken.max_movable_run(2), 2);
+        assert!(!broken.is_valid_run(0));
+        assert!(broken.is_valid_run(2));
+    }
+
+    #[cfg(not(feature = "xoshiro_shuffle"))]
+    #[test]
+    fn test_winable_seeds_are_sorted() {
+        assert!(WINABLE_SEEDS.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(WINABLE_SEEDS.iter().all(|seed| is_known_winnable(*seed)));
+        assert!(!is_known_winnable(TEST_SEED));
+    }
+
+    #[test]
+    fn test_rank_neighbors() {
+        assert_eq!(Rank::Ace.successor(), Some(Rank::Two));
+        assert_eq!(Rank::King.successor(), None);
+        assert_eq!(Rank::Ace.predecessor(), None);
+        assert_eq!(Rank::King.predecessor(), Some(Rank::Queen));
+        assert_eq!(Rank::Ace.value(), 1);
+        assert!(Rank::into_enum_iter().all(|rank| Rank::from_value(rank.value()) == Some(rank)));
+        assert_eq!(Rank::from_value(0), None);
+        assert_eq!(Rank::from_value(14), None);
+        assert_eq!(card(Rank::King, Suit::Heart, true).value(), 13);
+        let nine = card(Rank::Nine, Suit::Heart, true);
+        let ten = card(Rank::Ten, Suit::Club, true);
+        assert!(nine.is_one_below(&ten));
+        assert!(!ten.is_one_below(&nine));
+    }
+
+    #[test]
+    fn test_play_display() {
+        let play = Play::MoveCards(Source::new(StackId::Tableau3, 5), StackId::Foundation2);
+        assert_eq!(play.to_string(), "MOVE Tableau3[5] -> Foundation2");
+        assert_eq!(Play::DrawFromStock.to_string(), "DRAW");
+        assert_eq!(Play::RecycleWaste.to_string(), "RECYCLE");
+    }
+
+    // Every seed in WINABLE_SEEDS was found with this exact shuffle.
+    #[cfg(not(feature = "xoshiro_shuffle"))]
+    #[test]
+    fn test_make_deck_matches_historical_deck() {
+        let expected = "-6D -KC -7H -8C -6H -TS -2D -8S -AD -3H -KD -6S -QS -5C -2C -TD -5S -JH \
+            -3C -TC -7D -9C -4H -6C -5D -TH -2H -4S -9S -QC -2S -KS -JS -9D -7S -4D -JD -9H -3S \
+            -JC -3D -4C -8D -7C -AH -AC -QD -KH -AS -8H -QH -5H";
+        let expected = expected
+            .split_whitespace()
+            .map(Card::from_str)
+            .collect::<Result<Vec<Card>, _>>()
+            .expect("cards");
+        assert_eq!(make_deck(TEST_SEED, SUITS), expected);
+    }
+
+    #[test]
+    fn test_make_deck_with_same_rng_seed() {
+        let mut first = ShuffleRng::seed_from_u64(TEST_SEED);
+        let mut second = ShuffleRng::seed_from_u64(TEST_SEED);
+        let deck = make_deck_with(&mut first, SUITS);
+        assert_eq!(deck, make_deck_with(&mut second, SUITS));
+        assert_eq!(deck, make_deck(TEST_SEED, SUITS));
+        let mut rng = ShuffleRng::seed_from_u64(TEST_SEED);
+        let table = Table::from_rng(&mut rng);
+        assert_eq!(table.canonical_key(), Table::new(TEST_SEED).canonical_key());
+        assert_eq!(table.seed(), None);
+    }
+
+    #[test]
+    fn test_table_keeps_its_seed() {
+        assert_eq!(Table::new(TEST_SEED).seed(), Some(TEST_SEED));
+        assert_eq!(Table::new_two_suit(TEST_SEED).seed(), Some(TEST_SEED));
+        let table: Table = nearly_won_table().to_string().parse().expect("table");
+        assert_eq!(table.seed(), None);
+    }
+
+    #[test]
+    fn test_cards_remaining_to_win() {
+        assert_eq!(Table::new(TEST_SEED).cards_remaining_to_win(), 52);
+        let empty = Table::from_layout(Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        assert_eq!(empty.cards_remaining_to_win(), 0);
+        assert!(!empty.winner());
+        let mut table = nearly_won_table();
+        assert!(table.auto_complete());
+        assert_eq!(table.cards_remaining_to_win(), 0);
+    }
+
+    #[test]
+    fn test_two_suit_deal() {
+        assert_eq!(make_deck(TEST_SEED, TWO_SUITS).len(), 26);
+        let table = Table::new_two_suit(TEST_SEED);
+        assert_eq!(table.card_count(), 26);
+        assert_eq!(table.cards_remaining_to_win(), 26);
+        assert_eq!(table.tableaux[4].len(), 5);
+        assert!(table.tableaux[5].is_empty());
+        assert!(!table.winner());
+
+        let foundations = [Suit::Spade, Suit::Club, Suit::Heart]
+            .iter()
+            .map(|suit| match suit {
+                Suit::Spade | Suit::Heart => Rank::into_enum_iter()
+                    .map(|rank| card(rank, *suit, true))
+                    .collect(),
+                _ => Vec::new(),
+            })
+            .collect();
+        let table = Table::from_layout(Vec::new(), Vec::new(), foundations, Vec::new());
+        assert_eq!(table.cards_in_foundation(), 26);
+        assert!(table.winner());
     }
 
     #[test]
@@ -536,4 +1217,966 @@ mod test {
         let waste_card_location = table.find_card(Rank::Nine, Suit::Club);
         assert_eq!(Some(Source::new(StackId::Waste, 2)), waste_card_location);
     }
+
+    #[test]
+    fn test_active_sources() {
+        let mut table = Table::new(TEST_SEED);
+        let tops = TABLEAUX
+            .iter()
+            .enumerate()
+            .map(|(index, tableau)| Source::new(*tableau, index));
+        let expected: Vec<Source> = std::iter::once(Source::stock()).chain(tops).collect();
+        assert_eq!(
+            table
+                .active_sources(NavigationOrder::Standard)
+                .collect::<Vec<_>>(),
+            expected
+        );
+
+        table.deal_from_stock();
+        let active: Vec<Source> = table.active_sources(NavigationOrder::Standard).collect();
+        assert_eq!(
+            active[..2],
+            [Source::stock(), Source::new(StackId::Waste, 2)]
+        );
+        assert_eq!(active[2..], expected[1..]);
+
+        let active: Vec<Source> = table
+            .active_sources(NavigationOrder::TableauxFirst)
+            .collect();
+        assert_eq!(active[..7], expected[1..]);
+        assert_eq!(
+            active[7..],
+            [Source::stock(), Source::new(StackId::Waste, 2)]
+        );
+    }
+
+    #[test]
+    fn test_get_card_out_of_range() {
+        let table = Table::new(TEST_SEED);
+        let tableau = &table.tableaux[2];
+        assert_eq!(tableau.get_card(2), tableau.get_top_card());
+        assert_eq!(tableau.get_card(3), None);
+        assert_eq!(tableau.get_card(usize::MAX), None);
+
+        assert!(table.waste.is_empty());
+        assert_eq!(table.waste.get_top_card(), None);
+        assert_eq!(table.waste.top_card(), None);
+        assert_eq!(table.waste.bottom_card(), None);
+        assert_eq!(table.waste.get_card(0), None);
+    }
+
+    #[test]
+    fn test_face_up_cards() {
+        let mut table = Table::new(TEST_SEED);
+        for tableau in &table.tableaux {
+            assert_eq!(tableau.iter().count(), tableau.len());
+            assert_eq!(tableau.face_up_cards().count(), 1);
+        }
+        assert_eq!(table.stock.face_up_cards().count(), 0);
+
+        table.deal_from_stock();
+        let waste = &table.waste;
+        assert_eq!(waste.face_up_cards().count(), waste.len());
+        assert!((&table.stock).into_iter().all(|card| !card.face_up));
+    }
+
+    #[test]
+    fn test_reveal_all_leaves_the_stock() {
+        let mut table = Table::new(TEST_SEED);
+        let original = table.clone();
+        table.reveal_all();
+        assert!(table
+            .tableaux
+            .iter()
+            .all(|tableau| tableau.iter().all(|card| card.face_up)));
+        assert_eq!(table.stock, original.stock);
+        assert_eq!(table.count_face_down(), original.stock.len());
+    }
+
+    #[test]
+    fn test_expose_after_move() {
+        let mut table = Table::new(TEST_SEED);
+        let source = Source::new(StackId::Tableau5, 4);
+
+        table
+            .take_selected_cards_from_stack(source.stack, source.index)
+            .expect("take");
+        let top_card = table.get_stack(StackId::Tableau5).get_top_card();
+        assert!(!top_card.expect("top_card").face_up);
+
+        table
+            .put_hand_on_stack(source, StackId::Tableau1)
+            .expect("put");
+        let top_card = table.get_stack(StackId::Tableau5).get_top_card();
+        assert!(top_card.expect("top_card").face_up);
+        assert_eq!(table.get_stack(StackId::Tableau1).len(), 2);
+    }
+
+    #[test]
+    fn test_return_to_source_is_not_a_move() {
+        let table = Table::new(TEST_SEED);
+        let mut work_table = table.clone();
+        let source = Source::new(StackId::Tableau5, 4);
+
+        work_table
+            .take_selected_cards_from_stack(source.stack, source.index)
+            .expect("take");
+        work_table
+            .put_hand_on_stack(source, source.stack)
+            .expect("put");
+        assert_eq!(
+            table.get_stack(StackId::Tableau5),
+            work_table.get_stack(StackId::Tableau5)
+        );
+        assert_eq!(work_table.move_count(), table.move_count());
+    }
+
+    #[test]
+    fn test_cannot_lift_face_down_card() {
+        let table = Table::from_layout(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vec![vec![
+                card(Rank::King, Suit::Spade, false),
+                card(Rank::Queen, Suit::Heart, true),
+            ]],
+        );
+        let mut work_table = table.clone();
+
+        assert!(!work_table.can_select(Source::new(StackId::Tableau1, 0)));
+        assert_eq!(
+            work_table.take_selected_cards_from_stack(StackId::Tableau1, 0),
+            Err(MoveError::FaceDownCard)
+        );
+        assert!(!work_table.cards_in_hand());
+        assert_eq!(table, work_table);
+
+        assert_eq!(
+            work_table.take_selected_cards_from_stack(StackId::Tableau1, 2),
+            Err(MoveError::EmptyStack)
+        );
+        assert_eq!(table, work_table);
+
+        let active: Vec<Source> =
+            ActiveCardIterator::new(&work_table, NavigationOrder::Standard).collect();
+        assert_eq!(active, vec![Source::new(StackId::Tableau1, 1)]);
+    }
+
+    #[test]
+    fn test_move_errors() {
+        let table = Table::from_layout(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vec![
+                vec![card(Rank::Queen, Suit::Heart, true)],
+                vec![card(Rank::Jack, Suit::Diamond, true)],
+            ],
+        );
+        let mut work_table = table.clone();
+        assert_eq!(
+            work_table.take_top_card_from_stack(StackId::Waste),
+            Err(MoveError::EmptyStack)
+        );
+        assert_eq!(
+            work_table.put_hand_on_stack(Source::stock(), StackId::Tableau1),
+            Err(MoveError::EmptyStack)
+        );
+        assert_eq!(
+            work_table.apply_play(Play::DrawFromStock),
+            Err(MoveError::EmptyStack)
+        );
+
+        let play = Play::MoveCards(Source::new(StackId::Tableau2, 0), StackId::Tableau1);
+        assert_eq!(work_table.apply_play(play), Err(MoveError::IllegalTarget));
+        assert_eq!(table, work_table);
+    }
+
+    #[test]
+    fn test_recycle_waste_needs_an_empty_stock() {
+        let mut table = Table::new(TEST_SEED);
+        table.deal_from_stock();
+        assert!(table.has_cards_in_stock() && table.has_cards_in_waste());
+        assert!(!table.can_recycle());
+        let dealt = table.clone();
+        assert_eq!(
+            table.apply_play(Play::RecycleWaste),
+            Err(MoveError::EmptyStack)
+        );
+        assert_eq!(dealt, table);
+    }
+
+    #[test]
+    fn test_stock_cards_cannot_be_moved() {
+        let stock = vec![
+            card(Rank::Queen, Suit::Spade, false),
+            card(Rank::King, Suit::Heart, false),
+        ];
+        let table = Table::from_layout(stock, Vec::new(), Vec::new(), Vec::new());
+        let mut work_table = table.clone();
+        for index in 0..2 {
+            let source = Source::new(StackId::Stock, index);
+            assert!(!work_table.can_select(source));
+            let play = Play::MoveCards(source, StackId::Tableau1);
+            assert_eq!(work_table.apply_play(play), Err(MoveError::FaceDownCard));
+            assert_eq!(
+                work_table.take_selected_cards_from_stack(StackId::Stock, index),
+                Err(MoveError::FaceDownCard)
+            );
+        }
+        assert_eq!(table, work_table);
+    }
+
+    #[test]
+    fn test_normalize_exposes_tableau_tops() {
+        let mut table = Table::from_layout(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vec![
+                vec![
+                    card(Rank::King, Suit::Spade, false),
+                    card(Rank::Queen, Suit::Heart, false),
+                ],
+                vec![card(Rank::Ace, Suit::Club, true)],
+            ],
+        );
+        let face_up = |table: &Table, stack_id, index| {
+            table.get_stack(stack_id).get_card(index).unwrap().face_up
+        };
+        table.normalize();
+        assert!(face_up(&table, StackId::Tableau1, 1));
+        assert!(!face_up(&table, StackId::Tableau1, 0));
+        assert!(face_up(&table, StackId::Tableau2, 0));
+
+        let normalized = table.clone();
+        table.normalize();
+        assert_eq!(table, normalized);
+
+        table.source = Source::new(StackId::Tableau1, 1);
+        table
+            .take_selected_cards_from_stack(StackId::Tableau1, 1)
+            .expect("take");
+        table.normalize();
+        assert!(!face_up(&table, StackId::Tableau1, 0));
+    }
+
+    #[test]
+    fn test_filter_lone_card_without_king() {
+        let table = |waste| {
+            Table::from_layout(
+                Vec::new(),
+                waste,
+                Vec::new(),
+                vec![
+                    vec![card(Rank::Eight, Suit::Spade, true)],
+                    vec![
+                        card(Rank::Five, Suit::Diamond, false),
+                        card(Rank::Nine, Suit::Heart, true),
+                    ],
+                    vec![
+                        card(Rank::Two, Suit::Diamond, false),
+                        card(Rank::Eight, Suit::Club, true),
+                    ],
+                ],
+            )
+        };
+        let lone = Play::MoveCards(Source::new(StackId::Tableau1, 0), StackId::Tableau2);
+        let exposing = Play::MoveCards(Source::new(StackId::Tableau3, 1), StackId::Tableau2);
+        let node = |table| SearchNode::new(None, 0, Play::Setup, table, &Weights::default());
+
+        let stuck = table(Vec::new());
+        let stuck_node = node(&stuck);
+        assert_eq!(stuck_node.filter_play(&lone, &stuck, &Vec::new()), None);
+        assert_eq!(
+            stuck_node.filter_play(&exposing, &stuck, &Vec::new()),
+            Some(exposing)
+        );
+
+        let king = table(vec![card(Rank::King, Suit::Club, true)]);
+        assert_eq!(
+            node(&king).filter_play(&lone, &king, &Vec::new()),
+            Some(lone)
+        );
+    }
+
+    #[test]
+    fn test_navigation_orders_visit_every_stack_once() {
+        let all: HashSet<StackId> = StackId::into_enum_iter()
+            .filter(|stack_id| *stack_id != StackId::Hand)
+            .collect();
+        for order in [NavigationOrder::Standard, NavigationOrder::TableauxFirst] {
+            let mut forward = vec![order.first()];
+            let mut backward = vec![order.first()];
+            for _ in 1..all.len() {
+                let last = *forward.last().unwrap();
+                forward.push(order.next(last, true).expect("next"));
+                backward.push(order.previous(*backward.last().unwrap()));
+            }
+            for visited in [&forward, &backward] {
+                assert_eq!(visited.iter().copied().collect::<HashSet<_>>(), all);
+            }
+            let last = *forward.last().unwrap();
+            assert_eq!(order.next(last, true), Some(order.first()));
+            assert_eq!(order.next(last, false), None);
+            assert_eq!(order.previous(*backward.last().unwrap()), order.first());
+            assert_eq!(order.next(StackId::Hand, true), Some(StackId::Hand));
+        }
+        assert_eq!(StackId::Tableau7.next(), StackId::Stock);
+        assert_eq!(StackId::Stock.previous(), StackId::Tableau7);
+    }
+
+    #[test]
+    fn test_can_move() {
+        let table = Table::from_layout(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vec![
+                vec![card(Rank::Ace, Suit::Spade, true)],
+                vec![
+                    card(Rank::Two, Suit::Heart, false),
+                    card(Rank::Ace, Suit::Heart, true),
+                ],
+                vec![
+                    card(Rank::Ace, Suit::Club, true),
+                    card(Rank::King, Suit::Diamond, true),
+                    card(Rank::Queen, Suit::Club, true),
+                ],
+            ],
+        );
+
+        assert!(table.can_move(Source::new(StackId::Tableau1, 0), StackId::Foundation1));
+        assert!(!table.can_move(Source::new(StackId::Tableau1, 0), StackId::Foundation2));
+        assert!(table.can_move(Source::new(StackId::Tableau2, 1), StackId::Foundation3));
+        assert!(!table.can_move(Source::new(StackId::Tableau2, 0), StackId::Foundation3));
+        assert!(!table.can_move(Source::new(StackId::Tableau3, 0), StackId::Foundation2));
+        assert!(table.can_move(Source::new(StackId::Tableau3, 1), StackId::Tableau4));
+        assert!(!table.can_move(Source::new(StackId::Tableau3, 2), StackId::Tableau4));
+        assert!(!table.can_move(Source::new(StackId::Tableau3, 1), StackId::Tableau3));
+    }
+
+    #[test]
+    fn test_strict_runs_only_offer_run_starts() {
+        let mut table = Table::from_layout(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vec![vec![
+                card(Rank::Five, Suit::Diamond, false),
+                card(Rank::King, Suit::Spade, true),
+                card(Rank::Queen, Suit::Heart, true),
+                card(Rank::Jack, Suit::Spade, true),
+            ]],
+        );
+
+        let active: Vec<Source> =
+            ActiveCardIterator::new(&table, NavigationOrder::Standard).collect();
+        assert_eq!(
+            active,
+            vec![
+                Source::new(StackId::Tableau1, 1),
+                Source::new(StackId::Tableau1, 2),
+                Source::new(StackId::Tableau1, 3),
+            ]
+        );
+
+        table.options.strict_runs = true;
+        let active: Vec<Source> =
+            ActiveCardIterator::new(&table, NavigationOrder::Standard).collect();
+        assert_eq!(active, vec![Source::new(StackId::Tableau1, 1)]);
+
+        let mut work_table = table.clone();
+        assert_eq!(
+            work_table.take_selected_cards_from_stack(StackId::Tableau1, 2),
+            Err(MoveError::InvalidSequence)
+        );
+        assert_eq!(table, work_table);
+        work_table
+            .take_selected_cards_from_stack(StackId::Tableau1, 1)
+            .expect("take");
+        assert_eq!(work_table.in_hand.len(), 3);
+    }
+
+    #[test]
+    fn test_strict_runs_start_past_a_broken_run() {
+        let mut table = Table::from_layout(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vec![vec![
+                card(Rank::Five, Suit::Diamond, false),
+                card(Rank::King, Suit::Spade, true),
+                card(Rank::Five, Suit::Heart, true),
+                card(Rank::Four, Suit::Spade, true),
+            ]],
+        );
+        table.options.strict_runs = true;
+        let active: Vec<Source> =
+            ActiveCardIterator::new(&table, NavigationOrder::Standard).collect();
+        assert_eq!(active, vec![Source::new(StackId::Tableau1, 2)]);
+    }
+
+    fn auto_complete_table(stock: Vec<Card>, waste: Vec<Card>) -> Table {
+        let foundation = |suit| {
+            Rank::into_enum_iter()
+                .take(10)
+                .map(|rank| card(rank, suit, true))
+                .collect::<Vec<Card>>()
+        };
+        Table::from_layout(
+            stock,
+            waste,
+            vec![
+                foundation(Suit::Spade),
+                foundation(Suit::Club),
+                foundation(Suit::Heart),
+                foundation(Suit::Diamond),
+            ],
+            vec![
+                vec![
+                    card(Rank::King, Suit::Spade, true),
+                    card(Rank::Queen, Suit::Heart, true),
+                    card(Rank::Jack, Suit::Spade, true),
+                ],
+                vec![
+                    card(Rank::King, Suit::Heart, true),
+                    card(Rank::Queen, Suit::Spade, true),
+                    card(Rank::Jack, Suit::Heart, true),
+                ],
+                vec![card(Rank::King, Suit::Club, true)],
+            ],
+        )
+    }
+
+    #[test]
+    fn test_auto_complete() {
+        let mut table = auto_complete_table(
+            vec![
+                card(Rank::Jack, Suit::Club, false),
+                card(Rank::Queen, Suit::Club, false),
+            ],
+            vec![
+                card(Rank::King, Suit::Diamond, true),
+                card(Rank::Queen, Suit::Diamond, true),
+                card(Rank::Jack, Suit::Diamond, true),
+            ],
+        );
+
+        assert!(table.can_auto_complete());
+        assert!(table.auto_complete());
+        assert!(table.winner());
+    }
+
+    // Turns a buried card face down, as though it had never been turned up.
+    fn turn_down(stack: &mut Stack, index: usize) {
+        let mut above = Vec::new();
+        while stack.len() > index + 1 {
+            above.push(stack.pop_card().expect("pop_card"));
+        }
+        stack.flip_top_card();
+        while let Some(card) = above.pop() {
+            stack.push_card(card);
+        }
+    }
+
+    fn nearly_won_table() -> Table {
+        auto_complete_table(
+            vec![
+                card(Rank::Jack, Suit::Club, false),
+                card(Rank::Queen, Suit::Club, false),
+            ],
+            vec![
+                card(Rank::King, Suit::Diamond, true),
+                card(Rank::Queen, Suit::Diamond, true),
+                card(Rank::Jack, Suit::Diamond, true),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_canonical_key_ignores_move_order() {
+        let spade = Play::MoveCards(Source::new(StackId::Tableau1, 2), StackId::Foundation1);
+        let heart = Play::MoveCards(Source::new(StackId::Tableau2, 2), StackId::Foundation3);
+        let table = nearly_won_table();
+        let mut first = table.clone();
+        let mut second = table.clone();
+        for (table, plays) in [(&mut first, [spade, heart]), (&mut second, [heart, spade])] {
+            for play in plays.iter() {
+                table.apply_play(*play).expect("apply_play");
+            }
+        }
+        second.source = Source::new(StackId::Tableau3, 0);
+        assert_ne!(first, second);
+        assert_eq!(first.canonical_key(), second.canonical_key());
+        assert_ne!(first.canonical_key(), table.canonical_key());
+
+        let mut flipped = table.clone();
+        flipped.stock.flip_top_card();
+        assert_ne!(flipped.canonical_key(), table.canonical_key());
+    }
+
+    #[test]
+    fn test_canonical_key_counts_redeals_left() {
+        let redealt = |redeal_limit| {
+            let mut table = Table::new(TEST_SEED);
+            table.options.redeal_limit = redeal_limit;
+            let dealt = table.clone();
+            while table.has_cards_in_stock() {
+                table.deal_from_stock();
+            }
+            table.apply_play(Play::RecycleWaste).expect("recycle");
+            (dealt, table)
+        };
+        let (dealt, table) = redealt(None);
+        assert_eq!(dealt.canonical_key(), table.canonical_key());
+        let (dealt, table) = redealt(Some(2));
+        assert_eq!(dealt.stock, table.stock);
+        assert_ne!(dealt.canonical_key(), table.canonical_key());
+    }
+
+    #[test]
+    fn test_completing_a_suit_emits_an_event() {
+        let mut table = nearly_won_table();
+        assert_eq!(table.last_event(), None);
+        for index in (0..3).rev() {
+            assert_eq!(table.last_event(), None);
+            let play = Play::MoveCards(Source::new(StackId::Waste, index), StackId::Foundation4);
+            table.apply_play(play).expect("apply_play");
+        }
+        assert_eq!(
+            table.last_event(),
+            Some(GameEvent::FoundationCompleted(Suit::Diamond))
+        );
+
+        turn_down(&mut table.tableaux[0], 1);
+        let spade = Play::MoveCards(Source::new(StackId::Tableau1, 2), StackId::Foundation1);
+        table.apply_play(spade).expect("apply_play");
+        assert_eq!(table.last_event(), Some(GameEvent::CardFlipped));
+
+        table.deal_from_stock();
+        assert_eq!(table.last_event(), None);
+        table.recycle_waste();
+        assert_eq!(table.last_event(), Some(GameEvent::Recycled));
+
+        table.auto_complete();
+        assert!(table.winner());
+        assert!(matches!(
+            table.last_event(),
+            Some(GameEvent::FoundationCompleted(_))
+        ));
+    }
+
+    #[test]
+    fn test_equivalent_ignoring_cursor() {
+        let table = nearly_won_table();
+        let mut moved = table.clone();
+        moved.source = Source::new(StackId::Tableau2, 2);
+        moved.target = StackId::Foundation3;
+        assert_ne!(moved, table);
+        assert!(moved.equivalent_ignoring_cursor(&table));
+
+        let mut dealt = table.clone();
+        dealt.deal_from_stock();
+        assert!(!dealt.equivalent_ignoring_cursor(&table));
+    }
+
+    #[test]
+    fn test_unapply_restores_every_play() {
+        let check = |table: &mut Table, play: Play| {
+            let before = table.clone();
+            let token = table.apply(play).expect("apply");
+            assert_ne!(*table, before, "{}", play);
+            table.unapply(token);
+            assert_eq!(*table, before, "{}", play);
+            table.apply(play).expect("apply");
+        };
+
+        let mut table = Table::new(TEST_SEED);
+        table.options.draw_one = true;
+        check(&mut table, Play::DrawFromStock);
+        table.options.draw_one = false;
+        while table.has_cards_in_stock() {
+            check(&mut table, Play::DrawFromStock);
+        }
+        check(&mut table, Play::RecycleWaste);
+        let setup = table.clone();
+        let token = table.apply(Play::Setup).expect("apply");
+        table.unapply(token);
+        assert_eq!(table, setup);
+
+        // Every play from here, including one that turns up a card.
+        let mut table = nearly_won_table();
+        turn_down(&mut table.tableaux[0], 1);
+        let plays: Vec<Play> = PlayIterator::new(&table).collect();
+        assert!(plays.len() > 3);
+        for play in plays {
+            check(&mut table.clone(), play);
+        }
+        while let Some(play) = table.next_auto_complete_play() {
+            check(&mut table, play);
+        }
+        assert!(table.winner());
+    }
+
+    #[test]
+    fn test_resumed_search_matches_uninterrupted() {
+        let table = nearly_won_table();
+        let search = || Search::new(TEST_SEED, table.clone(), Weights::default());
+        let plays = test_plays_iter(search(), false, false, None);
+        assert!(plays.is_some());
+
+        let path = env::temp_dir().join(format!("checkpoint_test_{}.json", process::id()));
+        let checkpointed = test_plays_iter(search(), false, false, Some((2, &path)));
+        assert_eq!(checkpointed, plays);
+
+        let resumed = Search::load(&path).expect("load");
+        fs::remove_file(&path).expect("remove_file");
+        assert!(!path.with_extension("json.tmp").exists());
+        assert!(resumed.iterations > 0);
+        assert!(resumed.seen.len() > 1);
+        assert_eq!(test_plays_iter(resumed, false, false, None), plays);
+    }
+
+    #[test]
+    fn test_resume_rejects_other_weights() {
+        let saved = Weights {
+            waste: 7,
+            ..Weights::default()
+        };
+        assert!(check_resumed_weights(&saved, &saved).is_ok());
+        assert!(check_resumed_weights(&saved, &Weights::default()).is_ok());
+        let requested = Weights {
+            foundation: 3,
+            ..Weights::default()
+        };
+        assert!(check_resumed_weights(&saved, &requested).is_err());
+    }
+
+    #[test]
+    fn test_parsed_board_round_trips_through_solver() {
+        let table = nearly_won_table();
+        let text = table.to_string();
+        assert!(text.contains("Stock: -JC -QC\n"));
+        let parsed: Table = text.parse().expect("parse");
+        assert_eq!(parsed, table);
+
+        let search = |table: &Table| Search::new(TEST_SEED, table.clone(), Weights::default());
+        let plays = test_plays_iter(search(&table), false, false, None);
+        assert!(plays.is_some());
+        assert_eq!(test_plays_iter(search(&parsed), false, false, None), plays);
+
+        assert!("Tableau8: AS".parse::<Table>().is_err());
+        assert!(text.replace("-QC", "-1C").parse::<Table>().is_err());
+        assert!(text.replace("-QC", "-JC").parse::<Table>().is_err());
+        assert!(text.replace(" -QC", "").parse::<Table>().is_err());
+    }
+
+    #[test]
+    fn test_two_suit_board_round_trips() {
+        let table = Table::new_two_suit(TEST_SEED);
+        let text = table.to_string();
+        let parsed: Table = text.parse().expect("parse");
+        assert_eq!(parsed.to_string(), text);
+        assert_eq!(parsed.canonical_key(), table.canonical_key());
+        assert_eq!(parsed.card_count(), TWO_SUITS.len() * 13);
+
+        let first_card = text.split_whitespace().nth(3).expect("card");
+        let missing = text.replacen(&format!(" {}", first_card), "", 1);
+        assert!(missing.parse::<Table>().is_err());
+        assert!("".parse::<Table>().is_err());
+
+        // Without the deck size it's a full deck with two suits missing.
+        assert!(text.starts_with("Deck: 26\n"));
+        assert!(text.replacen("Deck: 26\n", "", 1).parse::<Table>().is_err());
+    }
+
+    #[test]
+    fn test_optimal_plays_are_no_longer_than_greedy() {
+        let table = nearly_won_table();
+        let greedy = test_plays_iter(
+            Search::new(TEST_SEED, table.clone(), Weights::default()),
+            false,
+            false,
+            None,
+        )
+        .expect("greedy");
+        let optimal = optimal_plays(&table, false).expect("optimal");
+        assert!(optimal.len() <= greedy.len());
+        assert_eq!(optimal.len(), table.cards_remaining_to_win() + 1);
+
+        let mut table = table;
+        for play in optimal {
+            apply(&mut table, play);
+        }
+        assert!(table.winner());
+    }
+
+    #[test]
+    fn test_auto_complete_gives_up_on_buried_waste() {
+        // Dealing three at a time always leaves the queen on the jack.
+        let table = auto_complete_table(
+            vec![
+                card(Rank::Queen, Suit::Club, false),
+                card(Rank::Jack, Suit::Club, false),
+            ],
+            vec![
+                card(Rank::King, Suit::Diamond, true),
+                card(Rank::Queen, Suit::Diamond, true),
+                card(Rank::Jack, Suit::Diamond, true),
+            ],
+        );
+
+        assert!(!table.can_auto_complete());
+        assert!(!Table::new(TEST_SEED).can_auto_complete());
+    }
+
+    #[test]
+    fn test_safe_to_foundation() {
+        let up_to = |rank: Rank, suit| {
+            Rank::into_enum_iter()
+                .take(rank.value() as usize)
+                .map(|rank| card(rank, suit, true))
+                .collect::<Vec<Card>>()
+        };
+        let table = |diamonds| {
+            Table::from_layout(
+                Vec::new(),
+                Vec::new(),
+                vec![
+                    up_to(Rank::Five, Suit::Spade),
+                    up_to(Rank::Ace, Suit::Club),
+                    up_to(Rank::Five, Suit::Heart),
+                    up_to(diamonds, Suit::Diamond),
+                ],
+                vec![
+                    vec![card(Rank::Six, Suit::Spade, true)],
+                    vec![card(Rank::Two, Suit::Club, true)],
+                ],
+            )
+        };
+        let six = card(Rank::Six, Suit::Spade, true);
+        let two = card(Rank::Two, Suit::Club, true);
+
+        let mut reds_up = table(Rank::Five);
+        assert!(reds_up.is_safe_to_foundation(&six));
+        reds_up.options.strict_safety = true;
+        assert!(!reds_up.is_safe_to_foundation(&six));
+        assert!(reds_up.is_safe_to_foundation(&two));
+
+        let mut reds_behind = table(Rank::Four);
+        assert!(!reds_behind.is_safe_to_foundation(&six));
+        assert!(reds_behind.is_safe_to_foundation(&two));
+        assert_eq!(reds_behind.auto_play_to_foundations(), 1);
+        assert_eq!(table(Rank::Five).auto_play_to_foundations(), 2);
+        assert_eq!(table(Rank::Five).vegas_score(), 5 * 16 - 52);
+    }
+
+    #[cfg(not(feature = "xoshiro_shuffle"))]
+    #[test]
+    fn test_first_winnable_seeds_solve() {
+        let opt = Opt::from_args(&["klondike_solver"], &[]).expect("default options");
+        assert_eq!(unsolved_seeds(&WINABLE_SEEDS[..3], &opt), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn test_card_label() {
+        let labels: Vec<String> = [
+            card(Rank::Ace, Suit::Spade, true),
+            card(Rank::Ten, Suit::Heart, true),
+            card(Rank::Jack, Suit::Club, true),
+            card(Rank::Queen, Suit::Diamond, false),
+            card(Rank::King, Suit::Spade, true),
+        ]
+        .iter()
+        .map(Card::label)
+        .collect();
+        assert_eq!(labels, vec!["A♠", "T♥", "J♣", "Q♦", "K♠"]);
+        let ten = card(Rank::Ten, Suit::Heart, true);
+        assert_ne!(ten.label(), format!("{:?}", ten));
+    }
+
+    #[test]
+    fn test_moved_card() {
+        let table = Table::new(TEST_SEED);
+        let top = table.get_stack(StackId::Tableau7).top_card().cloned();
+        let play = Play::MoveCards(Source::new(StackId::Tableau7, 6), StackId::Tableau1);
+        assert_eq!(table.moved_card(play), top);
+        let past_top = Play::MoveCards(Source::new(StackId::Tableau7, 7), StackId::Tableau1);
+        assert_eq!(table.moved_card(past_top), None);
+        assert_eq!(table.moved_card(Play::DrawFromStock), None);
+    }
+
+    #[test]
+    fn test_random_games_keep_the_deck_whole() {
+        for seed in 0..40 {
+            let mut table = Table::new(seed);
+            let mut rng = ShuffleRng::seed_from_u64(seed);
+            if let Err(error) = table.play_random_game(&mut rng, 400) {
+                panic!("seed {}: {}", seed, error);
+            }
+        }
+    }
+
+    #[test]
+    fn test_legal_plays_all_apply() {
+        let table = Table::new(TEST_SEED);
+        let plays: Vec<Play> = table.legal_plays().collect();
+        assert_eq!(plays.first(), Some(&Play::DrawFromStock));
+        for play in plays {
+            let mut copy = table.clone();
+            assert_eq!(copy.apply_play(play), Ok(()), "{}", play);
+        }
+    }
+
+    #[test]
+    fn test_foundation_for_suit() {
+        let ace = card(Rank::Ace, Suit::Heart, true);
+        let mut table = Table::from_layout(vec![], vec![ace], vec![], vec![]);
+        assert_eq!(table.foundation_for_suit(Suit::Heart), None);
+        assert_eq!(
+            table.apply_play(Play::MoveCards(
+                Source::new(StackId::Waste, 0),
+                StackId::Foundation3
+            )),
+            Ok(())
+        );
+        assert_eq!(
+            table.foundation_for_suit(Suit::Heart),
+            Some(StackId::Foundation3)
+        );
+        assert_eq!(table.foundation_for_suit(Suit::Spade), None);
+
+        let table = nearly_won_table();
+        for suit in SUITS {
+            let foundation = table.foundation_for_suit(*suit);
+            assert_eq!(foundation.and_then(|id| id.foundation_suit()), Some(*suit));
+        }
+    }
+
+    #[test]
+    fn test_fresh_deal_counts_face_down() {
+        let table = Table::new(TEST_SEED);
+        let stock = table.get_stack(StackId::Stock).len();
+        assert_eq!(stock, 24);
+        assert_eq!(table.count_face_down(), 21 + stock);
+        assert_eq!(nearly_won_table().count_face_down(), 2);
+    }
+
+    #[test]
+    fn test_push_card_builds_foundations_in_order() {
+        let mut table = Table::from_layout(vec![], vec![], vec![], vec![]);
+        let foundation = table.get_stack_mut(StackId::Foundation1);
+        foundation.push_card(card(Rank::Ace, Suit::Spade, true));
+        foundation.push_card(card(Rank::Two, Suit::Spade, true));
+        assert_eq!(foundation.len(), 2);
+        assert_eq!(foundation.pop_card().map(|card| card.rank), Some(Rank::Two));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn test_push_card_rejects_the_wrong_suit_on_a_foundation() {
+        let mut table = Table::from_layout(vec![], vec![], vec![], vec![]);
+        let foundation = table.get_stack_mut(StackId::Foundation1);
+        foundation.push_card(card(Rank::Ace, Suit::Spade, true));
+        foundation.push_card(card(Rank::Two, Suit::Heart, true));
+    }
+
+    #[test]
+    fn test_dead_end_has_no_legal_move() {
+        let table = Table::dead_end();
+        assert!(!table.has_any_legal_move());
+        assert!(!table.winner());
+        assert!(!table.has_cards_in_stock() && !table.has_cards_in_waste());
+        assert!(Table::new(TEST_SEED).has_any_legal_move());
+        assert!(nearly_won_table().has_any_legal_move());
+    }
+
+    #[test]
+    fn test_foundation_runs_option() {
+        let table = |foundation_runs, second| {
+            let mut table = Table::from_layout(
+                vec![],
+                vec![],
+                vec![vec![card(Rank::Ace, Suit::Spade, true)]],
+                vec![vec![card(Rank::Two, Suit::Spade, true), second]],
+            );
+            table.options.foundation_runs = foundation_runs;
+            table
+        };
+        let source = Source::new(StackId::Tableau1, 0);
+        let run = Play::MoveCards(source, StackId::Foundation1);
+        let three = card(Rank::Three, Suit::Spade, true);
+
+        let mut strict = table(false, three.clone());
+        assert!(!strict.can_move(source, StackId::Foundation1));
+        assert_eq!(strict.apply_play(run), Err(MoveError::IllegalTarget));
+
+        let mut runs = table(true, three);
+        assert!(runs.can_move(source, StackId::Foundation1));
+        assert_eq!(runs.apply_play(run), Ok(()));
+        assert_eq!(runs.get_stack(StackId::Foundation1).len(), 3);
+
+        // Mixed suits still can't go up together.
+        let mut mixed = table(true, card(Rank::Three, Suit::Heart, true));
+        assert!(!mixed.can_move(source, StackId::Foundation1));
+        assert_eq!(mixed.apply_play(run), Err(MoveError::IllegalTarget));
+    }
+
+    #[test]
+    fn test_hint_chain_starts_with_the_suggested_move() {
+        let table = nearly_won_table();
+        let chain = table.hint_chain(HINT_DEPTH);
+        assert_eq!(chain.len(), 4);
+        // The waste holds the diamonds from the king down, the jack on top.
+        let jack_up = Play::MoveCards(Source::new(StackId::Waste, 2), StackId::Foundation4);
+        assert_eq!(chain.first().copied(), Some(jack_up));
+        assert_eq!(table.suggest_move(), Some(jack_up));
+        let mut played = table.clone();
+        for play in &chain {
+            assert_eq!(played.apply_play(*play), Ok(()), "{}", play);
+        }
+        assert_eq!(
+            played.cards_in_foundation(),
+            table.cards_in_foundation() + 4
+        );
+
+        assert_eq!(Table::dead_end().hint_chain(HINT_DEPTH), vec![]);
+        assert_eq!(Table::dead_end().suggest_move(), None);
+    }
+
+    #[test]
+    fn test_hint_starts_with_a_draw_when_the_plan_does() {
+        // Everything is home but the diamonds from the jack up, which are
+        // still in the stock; drawing scores nothing until they go up.
+        let foundations = SUITS
+            .iter()
+            .map(|suit| {
+                Rank::into_enum_iter()
+                    .filter(|rank| *suit != Suit::Diamond || *rank < Rank::Jack)
+                    .map(|rank| card(rank, *suit, true))
+                    .collect()
+            })
+            .collect();
+        // Drawn three at once, so the jack lands on top of the waste.
+        let stock = vec![
+            card(Rank::Jack, Suit::Diamond, false),
+            card(Rank::Queen, Suit::Diamond, false),
+            card(Rank::King, Suit::Diamond, false),
+        ];
+        let table = Table::from_layout(stock, Vec::new(), foundations, Vec::new());
+        // A single play never finds it.
+        assert_eq!(table.hint_chain(1), vec![]);
+        let chain = table.hint_chain(HINT_DEPTH);
+        assert_eq!(chain.first(), Some(&Play::DrawFromStock));
+        assert_eq!(table.suggest_move(), Some(Play::DrawFromStock));
+        let mut played = table.clone();
+        for play in &chain {
+            assert_eq!(played.apply_play(*play), Ok(()), "{}", play);
+        }
+        assert!(played.winner());
+    }
 }
@@ -0,0 +1,66 @@
+#[cfg(not(feature = "xoshiro_shuffle"))]
+use crate::klondike::WINABLE_SEEDS;
+
+const SECONDS_PER_DAY: usize = 24 * 60 * 60;
+
+// The Playdate epoch is midnight, January 1, 2000.
+const DAYS_FROM_UNIX_EPOCH: i64 = 10_957;
+
+pub fn day_from_seconds(secs: usize) -> usize {
+    secs / SECONDS_PER_DAY
+}
+
+// Every day gets its own deal, always one known to be winnable.
+#[cfg(not(feature = "xoshiro_shuffle"))]
+pub fn daily_seed(day: usize) -> u64 {
+    WINABLE_SEEDS[day % WINABLE_SEEDS.len()]
+}
+
+// With no winnable list for this shuffle, the day is the seed.
+#[cfg(feature = "xoshiro_shuffle")]
+pub fn daily_seed(day: usize) -> u64 {
+    day as u64
+}
+
+// Returns (year, month, day of month) for a day since the Playdate epoch.
+pub fn date_from_day(day: usize) -> (i64, u32, u32) {
+    // Converted the same way as Howard Hinnant's civil_from_days.
+    let z = day as i64 + DAYS_FROM_UNIX_EPOCH + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_date_from_day() {
+        assert_eq!(date_from_day(0), (2000, 1, 1));
+        assert_eq!(date_from_day(59), (2000, 2, 29));
+        assert_eq!(date_from_day(366), (2001, 1, 1));
+        assert_eq!(date_from_day(9_783), (2026, 10, 14));
+    }
+
+    #[test]
+    fn test_daily_seed_ignores_time_of_day() {
+        let midnight = 9_783 * SECONDS_PER_DAY;
+        let seed = daily_seed(day_from_seconds(midnight));
+        assert_eq!(daily_seed(day_from_seconds(midnight + 1)), seed);
+        assert_eq!(
+            daily_seed(day_from_seconds(midnight + SECONDS_PER_DAY - 1)),
+            seed
+        );
+        assert_ne!(
+            daily_seed(day_from_seconds(midnight + SECONDS_PER_DAY)),
+            seed
+        );
+    }
+}
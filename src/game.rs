@@ -0,0 +1,1066 @@
+use crate::klondike::*;
+use alloc::{collections::VecDeque, format, string::String, vec::Vec};
+#[cfg(feature = "log_moves")]
+use crankstart::log_to_console;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    Previous,
+    Next,
+    Select,
+    Undo,
+    Cancel,
+}
+
+// Everything needed to play a game again from the deal.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Recording {
+    pub seed: u64,
+    pub plays: Vec<Play>,
+}
+
+// A won game, as much as anyone else needs to take on the same deal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GameResult {
+    pub seed: u64,
+    pub moves: u32,
+    pub elapsed_ms: usize,
+}
+
+impl GameResult {
+    // Space separated so it reads back easily, e.g.
+    // "Klondike #1234 142 moves 5:07".
+    pub fn to_share_string(self) -> String {
+        let seconds = self.elapsed_ms / 1000;
+        format!(
+            "Klondike #{} {} moves {}:{:02}",
+            self.seed,
+            self.moves,
+            seconds / 60,
+            seconds % 60
+        )
+    }
+}
+
+// The part of the game that doesn't need the Playdate, so that it can be
+// driven headlessly from tests.
+pub struct GameState {
+    pub table: Table,
+    active_cards: Vec<Source>,
+    source_index: usize,
+    targets: Vec<StackId>,
+    target_index: usize,
+    paused: bool,
+    // The clock waits for the first input after the deal.
+    started: bool,
+    elapsed_ms: usize,
+    recording: Recording,
+    // Goes up with every change to the recording, undo included, so a save
+    // can tell it's behind even when the move count is back where it was.
+    revision: u32,
+    // The table as it was before each move, most recent last.
+    history: Vec<Table>,
+    // The most cards that have been up on the foundations at once, undo or not.
+    furthest_progress: usize,
+    // And the fewest cards that have been left hidden.
+    fewest_face_down: usize,
+    // Held cards start on a foundation that takes them, ahead of the source.
+    foundations_first: bool,
+    // Moving onto an empty stock turns the waste back over right away.
+    auto_recycle: bool,
+    // Off, Previous and Next stop at the ends of the list instead.
+    wrap: bool,
+    // The order Previous and Next walk the stacks in.
+    navigation: NavigationOrder,
+    // Table::can_auto_complete plays the game out on a copy, so its answer
+    // is kept until the revision moves on.
+    auto_completable: Option<(u32, bool)>,
+    // Cards auto_play put up that haven't been shown yet, oldest first.
+    auto_plays: VecDeque<(Play, Option<Card>)>,
+}
+
+impl GameState {
+    pub fn new(seed: u64) -> Self {
+        Self::from_table(seed, Table::new(seed))
+    }
+
+    fn from_table(seed: u64, table: Table) -> Self {
+        #[cfg(feature = "log_moves")]
+        log_to_console!("DEAL {}", seed);
+        let navigation = NavigationOrder::default();
+        let active_cards = table.active_sources(navigation).collect();
+        let fewest_face_down = table.count_face_down();
+        Self {
+            table,
+            active_cards,
+            source_index: 0,
+            targets: Vec::new(),
+            target_index: 0,
+            paused: false,
+            started: false,
+            elapsed_ms: 0,
+            recording: Recording {
+                seed,
+                plays: Vec::new(),
+            },
+            revision: 0,
+            history: Vec::new(),
+            furthest_progress: 0,
+            fewest_face_down,
+            foundations_first: false,
+            auto_recycle: false,
+            wrap: true,
+            navigation,
+            auto_completable: None,
+            auto_plays: VecDeque::new(),
+        }
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    // The next play auto_play made on its own, and the card it put up.
+    pub fn next_auto_play(&mut self) -> Option<(Play, Option<Card>)> {
+        self.auto_plays.pop_front()
+    }
+
+    // Replays a single recorded move, leaving the cursor on whatever it
+    // touched.
+    pub fn apply_play(&mut self, play: Play) -> Result<(), MoveError> {
+        let before = self.table.clone();
+        self.table.apply_play(play)?;
+        self.history.push(before);
+        self.record(play);
+        self.table.source = match play {
+            Play::MoveCards(_, target) => {
+                let index = self.table.get_stack(target).top_card_index();
+                self.table.target = target;
+                Source::new(target, index)
+            }
+            _ => Source::stock(),
+        };
+        self.update_active_cards();
+        Ok(())
+    }
+
+    // Every move made, whether by the player or a replay, passes through here.
+    fn record(&mut self, play: Play) {
+        #[cfg(feature = "log_moves")]
+        log_to_console!("{}", play);
+        self.recording.plays.push(play);
+        self.revision = self.revision.wrapping_add(1);
+        self.furthest_progress = self.furthest_progress.max(self.table.cards_in_foundation());
+        self.fewest_face_down = self.fewest_face_down.min(self.table.count_face_down());
+    }
+
+    // Cards in hand always rule it out, so only an empty-handed answer is
+    // kept.
+    pub fn can_auto_complete(&mut self) -> bool {
+        if self.table.cards_in_hand() {
+            return false;
+        }
+        match self.auto_completable {
+            Some((revision, auto_completable)) if revision == self.revision => auto_completable,
+            _ => {
+                let auto_completable = self.table.can_auto_complete();
+                self.auto_completable = Some((self.revision, auto_completable));
+                auto_completable
+            }
+        }
+    }
+
+    // Short of a win with nothing left to play; undo may still find a way.
+    pub fn is_stuck(&self) -> bool {
+        !self.table.winner() && !self.table.cards_in_hand() && !self.table.has_any_legal_move()
+    }
+
+    pub fn result(&self) -> Option<GameResult> {
+        if !self.table.winner() {
+            return None;
+        }
+        Some(GameResult {
+            seed: self.recording.seed,
+            moves: self.table.move_count(),
+            elapsed_ms: self.elapsed_ms,
+        })
+    }
+
+    // The board as the solver's --from-stdin reads it, with the seed and
+    // every move so far as comments above it.
+    #[cfg(any(test, feature = "sim_debug"))]
+    pub fn board_dump(&self) -> String {
+        let mut dump = format!("# seed {}\n", self.recording.seed);
+        for play in &self.recording.plays {
+            dump += &format!("# {}\n", play);
+        }
+        dump + &format!("{}", self.table)
+    }
+
+    pub fn furthest_progress(&self) -> usize {
+        self.furthest_progress
+    }
+
+    pub fn fewest_face_down(&self) -> usize {
+        self.fewest_face_down
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn set_foundations_first(&mut self, foundations_first: bool) {
+        self.foundations_first = foundations_first;
+    }
+
+    pub fn set_navigation(&mut self, navigation: NavigationOrder) {
+        self.navigation = navigation;
+        self.update_active_cards();
+    }
+
+    // Meant for a fresh deal; options the preset doesn't cover are kept.
+    pub fn set_difficulty(&mut self, difficulty: Difficulty) {
+        let preset = TableOptions::from_difficulty(difficulty);
+        self.table.options.draw_one = preset.draw_one;
+        self.table.options.redeal_limit = preset.redeal_limit;
+        self.table.options.auto_play = preset.auto_play;
+        self.table.options.scoring = preset.scoring;
+    }
+
+    pub fn set_auto_recycle(&mut self, auto_recycle: bool) {
+        self.auto_recycle = auto_recycle;
+    }
+
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+
+    pub fn elapsed_ms(&self) -> usize {
+        self.elapsed_ms
+    }
+
+    // For a game picked back up from a save.
+    pub fn set_elapsed_ms(&mut self, elapsed_ms: usize) {
+        self.elapsed_ms = elapsed_ms;
+    }
+
+    pub fn advance(&mut self, delta_ms: usize) {
+        if self.started && !self.paused && !self.table.winner() {
+            self.elapsed_ms += delta_ms;
+        }
+    }
+
+    pub fn handle_input(&mut self, input: Input) {
+        if self.paused {
+            return;
+        }
+        self.started = true;
+        match input {
+            Input::Previous => self.go_previous(),
+            Input::Next => self.go_next(),
+            Input::Select => self.select(),
+            Input::Undo => self.undo(),
+            Input::Cancel => self.cancel(),
+        }
+    }
+
+    // Puts picked up cards back exactly as they were, cursor and all.
+    fn cancel(&mut self) {
+        if !self.table.cards_in_hand() {
+            return;
+        }
+        if let Some(table) = self.history.pop() {
+            self.restore(table);
+        }
+    }
+
+    fn restore(&mut self, table: Table) {
+        self.table = table;
+        self.update_active_cards();
+    }
+
+    fn undo(&mut self) {
+        if self.table.cards_in_hand() {
+            return;
+        }
+        if let Some(table) = self.history.pop() {
+            self.recording.plays.pop();
+            self.auto_plays.clear();
+            self.revision = self.revision.wrapping_add(1);
+            #[cfg(feature = "log_moves")]
+            log_to_console!("UNDO");
+            self.restore(table);
+        }
+    }
+
+    fn update_active_cards(&mut self) {
+        self.active_cards = self.table.active_sources(self.navigation).collect();
+        // A rebuild can shrink the list out from under the old index.
+        let source = self.table.source;
+        let last_index = self.active_cards.len().saturating_sub(1);
+        self.source_index = self
+            .active_cards
+            .iter()
+            .position(|active| *active == source)
+            .unwrap_or(self.source_index.min(last_index));
+        self.table.source = self
+            .active_cards
+            .get(self.source_index)
+            .copied()
+            .unwrap_or_else(Source::stock);
+    }
+
+    // Called before the source cards are picked up, so that the legality
+    // check is the same one the solver uses.
+    fn update_targets(&mut self) {
+        let source = self.table.source;
+
+        self.targets = self
+            .navigation
+            .stacks()
+            .iter()
+            .copied()
+            .filter(|stack_id| *stack_id == source.stack || self.table.can_move(source, *stack_id))
+            .collect();
+        if self.foundations_first {
+            // A stable sort, so the rest keep their order after the source.
+            self.targets.sort_by_key(|stack_id| {
+                let foundation = FOUNDATIONS.contains(stack_id);
+                (!foundation, *stack_id != source.stack)
+            });
+            self.target_index = 0;
+        } else {
+            self.target_index = self
+                .targets
+                .iter()
+                .position(|stack_id| *stack_id == source.stack)
+                .unwrap_or(0);
+        }
+        self.table.target = self
+            .targets
+            .get(self.target_index)
+            .copied()
+            .unwrap_or(source.stack);
+    }
+
+    fn go_previous(&mut self) {
+        self.step(if self.wrap {
+            previous_index
+        } else {
+            previous_index_clamped
+        });
+    }
+
+    fn go_next(&mut self) {
+        self.step(if self.wrap {
+            next_index
+        } else {
+            next_index_clamped
+        });
+    }
+
+    fn step(&mut self, advance: fn(usize, usize) -> usize) {
+        if self.table.cards_in_hand() {
+            if !self.targets.is_empty() {
+                self.target_index = advance(self.target_index, self.targets.len());
+                self.table.target = self.targets[self.target_index];
+            }
+        } else if self.active_cards.is_empty() {
+            // Never happens while the stock leads the list, but stays safe.
+            self.source_index = 0;
+            self.table.source = Source::stock();
+        } else {
+            self.source_index = advance(self.source_index, self.active_cards.len());
+            self.table.source = self.active_cards[self.source_index];
+            // Only an empty stock, so the fresh stock this leaves won't
+            // recycle again on the next visit.
+            if self.auto_recycle
+                && self.table.source.stack == StackId::Stock
+                && !self.table.has_cards_in_stock()
+            {
+                self.deal_from_stock();
+            }
+        }
+    }
+
+    fn deal_from_stock(&mut self) {
+        if self.table.has_cards_in_stock() || self.table.can_recycle() {
+            let play = if self.table.has_cards_in_stock() {
+                Play::DrawFromStock
+            } else {
+                Play::RecycleWaste
+            };
+            self.history.push(self.table.clone());
+            self.table.deal_from_stock();
+            self.record(play);
+            self.auto_play();
+            self.update_active_cards();
+        }
+    }
+
+    // Each card put up is a move of its own, so undo takes them back one
+    // at a time and a replay of the recording needs no autoplay.
+    fn auto_play(&mut self) {
+        if !self.table.options.auto_play {
+            return;
+        }
+        while let Some(play) = self.table.next_safe_foundation_play() {
+            let before = self.table.clone();
+            let card = self.table.moved_card(play);
+            if self.table.apply_play(play).is_err() {
+                return;
+            }
+            self.history.push(before);
+            self.record(play);
+            self.auto_plays.push_back((play, card));
+        }
+    }
+
+    fn select(&mut self) {
+        if self.table.cards_in_hand() {
+            let source = self.table.source;
+            let target = self.table.target;
+            if self.table.put_hand_on_target().is_err() {
+                return;
+            }
+            if source.stack != target {
+                self.record(Play::MoveCards(source, target));
+                self.auto_play();
+            } else {
+                self.history.pop();
+            }
+            self.update_active_cards();
+        } else {
+            self.table.target = self.table.source.stack;
+            self.update_targets();
+            let before = self.table.clone();
+            match self.table.source.stack {
+                StackId::Stock => self.deal_from_stock(),
+                StackId::Waste
+                | StackId::Foundation1
+                | StackId::Foundation2
+                | StackId::Foundation3
+                | StackId::Foundation4 => {
+                    let _ = self.table.take_top_card_from_stack(self.table.source.stack);
+                }
+                StackId::Tableau1
+                | StackId::Tableau2
+                | StackId::Tableau3
+                | StackId::Tableau4
+                | StackId::Tableau5
+                | StackId::Tableau6
+                | StackId::Tableau7 => {
+                    let _ = self.table.take_selected_cards_from_stack(
+                        self.table.source.stack,
+                        self.table.source.index,
+                    );
+                }
+                StackId::Hand => (),
+            }
+            // Picking up isn't a move yet; it's dropped from history again
+            // if the cards go back where they came from. A failed pickup
+            // leaves the hand empty and nothing to record.
+            if self.table.cards_in_hand() {
+                self.history.push(before);
+            }
+        }
+    }
+}
+
+// Both wrap around, and an index left stale past the end of a rebuilt list
+// lands back inside it. The list must not be empty.
+fn previous_index(index: usize, len: usize) -> usize {
+    if index == 0 || index >= len {
+        len - 1
+    } else {
+        index - 1
+    }
+}
+
+fn next_index(index: usize, len: usize) -> usize {
+    if index + 1 >= len {
+        0
+    } else {
+        index + 1
+    }
+}
+
+// The same, but stopping at the ends.
+fn previous_index_clamped(index: usize, len: usize) -> usize {
+    index.min(len).saturating_sub(1)
+}
+
+fn next_index_clamped(index: usize, len: usize) -> usize {
+    (index + 1).min(len - 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec;
+    use enum_iterator::IntoEnumIterator;
+
+    const TEST_SEED: u64 = 324;
+    const FRAME_MS: usize = 20;
+
+    fn card(rank: Rank, suit: Suit, face_up: bool) -> Card {
+        Card {
+            rank,
+            suit,
+            face_up,
+        }
+    }
+
+    #[test]
+    fn test_paused_time_does_not_count() {
+        let mut game = GameState::new(TEST_SEED);
+        game.handle_input(Input::Next);
+        for _ in 0..10 {
+            game.advance(FRAME_MS);
+        }
+        assert_eq!(game.elapsed_ms(), 10 * FRAME_MS);
+
+        game.set_paused(true);
+        for _ in 0..10 {
+            game.advance(FRAME_MS);
+        }
+        assert_eq!(game.elapsed_ms(), 10 * FRAME_MS);
+
+        game.set_paused(false);
+        game.advance(FRAME_MS);
+        assert_eq!(game.elapsed_ms(), 11 * FRAME_MS);
+    }
+
+    #[test]
+    fn test_clock_runs_from_first_input_until_win() {
+        // Everything but the king of diamonds is already home.
+        let foundations = [Suit::Spade, Suit::Club, Suit::Heart, Suit::Diamond]
+            .iter()
+            .map(|suit| {
+                Rank::into_enum_iter()
+                    .filter(|rank| *suit != Suit::Diamond || *rank != Rank::King)
+                    .map(|rank| card(rank, *suit, true))
+                    .collect()
+            })
+            .collect();
+        let king = card(Rank::King, Suit::Diamond, true);
+        let table = Table::from_layout(Vec::new(), Vec::new(), foundations, vec![vec![king]]);
+        let mut game = GameState::from_table(TEST_SEED, table);
+
+        game.advance(FRAME_MS);
+        assert_eq!(game.elapsed_ms(), 0);
+
+        select_source(&mut game, Source::new(StackId::Tableau1, 0));
+        game.advance(FRAME_MS);
+        assert_eq!(game.elapsed_ms(), FRAME_MS);
+
+        select_target(&mut game, StackId::Foundation4);
+        assert!(game.table.winner());
+        game.advance(FRAME_MS);
+        assert_eq!(game.elapsed_ms(), FRAME_MS);
+    }
+
+    #[test]
+    fn test_auto_complete_follows_undo() {
+        // Everything but the queen and king of diamonds is already home,
+        // with the king still hidden under the queen.
+        let foundations = [Suit::Spade, Suit::Club, Suit::Heart, Suit::Diamond]
+            .iter()
+            .map(|suit| {
+                Rank::into_enum_iter()
+                    .filter(|rank| *suit != Suit::Diamond || *rank < Rank::Queen)
+                    .map(|rank| card(rank, *suit, true))
+                    .collect()
+            })
+            .collect();
+        let tableau = vec![
+            card(Rank::King, Suit::Diamond, false),
+            card(Rank::Queen, Suit::Diamond, true),
+        ];
+        let table = Table::from_layout(Vec::new(), Vec::new(), foundations, vec![tableau]);
+        let mut game = GameState::from_table(TEST_SEED, table);
+        assert!(!game.can_auto_complete());
+
+        select_source(&mut game, Source::new(StackId::Tableau1, 1));
+        assert!(!game.can_auto_complete());
+        select_target(&mut game, StackId::Foundation4);
+        assert!(game.can_auto_complete());
+
+        game.handle_input(Input::Undo);
+        assert!(!game.can_auto_complete());
+    }
+
+    #[test]
+    fn test_share_string() {
+        let result = GameResult {
+            seed: 1234,
+            moves: 142,
+            elapsed_ms: 307_999,
+        };
+        assert_eq!(result.to_share_string(), "Klondike #1234 142 moves 5:07");
+        let result = GameResult {
+            seed: 7,
+            moves: 0,
+            elapsed_ms: 0,
+        };
+        assert_eq!(result.to_share_string(), "Klondike #7 0 moves 0:00");
+        assert_eq!(GameState::new(TEST_SEED).result(), None);
+    }
+
+    #[test]
+    fn test_board_dump_reads_back() {
+        let mut game = GameState::new(TEST_SEED);
+        game.handle_input(Input::Select);
+        game.handle_input(Input::Select);
+        let dump = game.board_dump();
+        let mut lines = dump.lines();
+        assert_eq!(lines.next(), Some("# seed 324"));
+        assert_eq!(lines.next(), Some("# DRAW"));
+        assert_eq!(lines.next(), Some("# DRAW"));
+        let table: Table = dump.parse().expect("parse");
+        assert!(table.equivalent_ignoring_cursor(&game.table));
+    }
+
+    #[test]
+    fn test_paused_ignores_input() {
+        let mut game = GameState::new(TEST_SEED);
+        let table = game.table.clone();
+
+        game.set_paused(true);
+        game.handle_input(Input::Select);
+        game.handle_input(Input::Next);
+        assert_eq!(game.table, table);
+
+        game.set_paused(false);
+        game.handle_input(Input::Next);
+        assert_ne!(game.table.source, table.source);
+    }
+
+    #[test]
+    fn test_navigate_after_emptying_source() {
+        let tableaux = vec![
+            vec![card(Rank::King, Suit::Spade, true)],
+            vec![],
+            vec![
+                card(Rank::Five, Suit::Diamond, false),
+                card(Rank::Nine, Suit::Club, true),
+            ],
+        ];
+        let table = Table::from_layout(Vec::new(), Vec::new(), Vec::new(), tableaux);
+        let mut game = GameState::from_table(TEST_SEED, table);
+
+        game.handle_input(Input::Next);
+        assert_eq!(game.table.source, Source::new(StackId::Tableau1, 0));
+        game.handle_input(Input::Select);
+        game.handle_input(Input::Next);
+        assert_eq!(game.table.target, StackId::Tableau2);
+        game.handle_input(Input::Select);
+        assert!(game.table.tableaux[0].is_empty());
+        assert_eq!(game.table.source, Source::new(StackId::Tableau2, 0));
+
+        for input in [Input::Next, Input::Next, Input::Previous, Input::Previous] {
+            game.handle_input(input);
+            assert!(game.source_index < game.active_cards.len());
+            let source = game.table.source;
+            assert!(source == Source::stock() || game.table.can_select(source));
+            assert_ne!(game.table.source.stack, StackId::Tableau1);
+        }
+    }
+
+    fn select_source(game: &mut GameState, source: Source) {
+        for _ in 0..game.active_cards.len() {
+            if game.table.source == source {
+                break;
+            }
+            game.handle_input(Input::Next);
+        }
+        assert_eq!(game.table.source, source);
+        game.handle_input(Input::Select);
+    }
+
+    fn select_target(game: &mut GameState, target: StackId) {
+        for _ in 0..game.targets.len() {
+            if game.table.target == target {
+                break;
+            }
+            game.handle_input(Input::Next);
+        }
+        assert_eq!(game.table.target, target);
+        game.handle_input(Input::Select);
+    }
+
+    #[test]
+    fn test_run_in_hand_never_targets_a_foundation() {
+        // The four of hearts would go up on its own, but not with the five.
+        let hearts = vec![
+            card(Rank::Ace, Suit::Heart, true),
+            card(Rank::Two, Suit::Heart, true),
+            card(Rank::Three, Suit::Heart, true),
+        ];
+        let table = Table::from_layout(
+            Vec::new(),
+            Vec::new(),
+            vec![Vec::new(), Vec::new(), hearts],
+            vec![
+                vec![
+                    card(Rank::Five, Suit::Spade, true),
+                    card(Rank::Four, Suit::Heart, true),
+                ],
+                vec![card(Rank::Six, Suit::Heart, true)],
+            ],
+        );
+        let mut game = GameState::from_table(TEST_SEED, table);
+        select_source(&mut game, Source::new(StackId::Tableau1, 0));
+        assert_eq!(game.table.hand_card_count(), 2);
+        assert_eq!(game.targets, vec![StackId::Tableau1, StackId::Tableau2]);
+        assert!(!game.table.stack_can_accept_hand(StackId::Foundation3));
+    }
+
+    #[test]
+    fn test_move_count_and_undo() {
+        let stock = vec![
+            card(Rank::Two, Suit::Diamond, false),
+            card(Rank::Three, Suit::Diamond, false),
+            card(Rank::Four, Suit::Diamond, false),
+        ];
+        let tableaux = vec![
+            vec![card(Rank::King, Suit::Spade, true)],
+            vec![card(Rank::Queen, Suit::Heart, true)],
+            vec![card(Rank::Jack, Suit::Club, true)],
+        ];
+        let table = Table::from_layout(stock, Vec::new(), Vec::new(), tableaux);
+        let mut game = GameState::from_table(TEST_SEED, table.clone());
+
+        select_source(&mut game, Source::stock());
+        select_source(&mut game, Source::new(StackId::Tableau2, 0));
+        select_target(&mut game, StackId::Tableau1);
+        select_source(&mut game, Source::new(StackId::Tableau3, 0));
+        select_target(&mut game, StackId::Tableau1);
+        assert_eq!(game.table.move_count(), 3);
+        assert_eq!(game.table.tableaux[0].len(), 3);
+
+        game.handle_input(Input::Undo);
+        assert_eq!(game.table.move_count(), 2);
+        assert_eq!(game.table.tableaux[2].len(), 1);
+
+        game.handle_input(Input::Undo);
+        game.handle_input(Input::Undo);
+        assert_eq!(game.table.move_count(), 0);
+        assert_eq!(game.table.stock, table.stock);
+        assert!(game.recording().plays.is_empty());
+        assert_eq!(game.revision(), 6);
+    }
+
+    #[test]
+    fn test_easy_plays_safe_cards_up() {
+        let tableaux = vec![
+            vec![
+                card(Rank::Ace, Suit::Heart, true),
+                card(Rank::Queen, Suit::Spade, true),
+            ],
+            vec![card(Rank::King, Suit::Heart, true)],
+            vec![card(Rank::Two, Suit::Heart, true)],
+        ];
+        let table = Table::from_layout(Vec::new(), Vec::new(), Vec::new(), tableaux);
+        let mut game = GameState::from_table(TEST_SEED, table);
+        game.set_difficulty(Difficulty::Easy);
+
+        select_source(&mut game, Source::new(StackId::Tableau1, 1));
+        select_target(&mut game, StackId::Tableau2);
+        assert_eq!(game.table.cards_in_foundation(), 2);
+        assert_eq!(game.recording().plays.len(), 3);
+
+        game.handle_input(Input::Undo);
+        assert_eq!(game.table.cards_in_foundation(), 1);
+        game.handle_input(Input::Undo);
+        game.handle_input(Input::Undo);
+        assert_eq!(game.table.tableaux[0].len(), 2);
+    }
+
+    #[test]
+    fn test_auto_plays_wait_to_be_shown() {
+        let tableaux = vec![
+            vec![
+                card(Rank::Ace, Suit::Heart, true),
+                card(Rank::Queen, Suit::Spade, true),
+            ],
+            vec![card(Rank::King, Suit::Heart, true)],
+            vec![card(Rank::Two, Suit::Heart, true)],
+        ];
+        let table = Table::from_layout(Vec::new(), Vec::new(), Vec::new(), tableaux);
+        let mut game = GameState::from_table(TEST_SEED, table.clone());
+        game.set_difficulty(Difficulty::Easy);
+
+        select_source(&mut game, Source::new(StackId::Tableau1, 1));
+        select_target(&mut game, StackId::Tableau2);
+        let shown: Vec<Option<Card>> = core::iter::from_fn(|| game.next_auto_play())
+            .map(|(_, card)| card)
+            .collect();
+        assert_eq!(
+            shown,
+            vec![
+                Some(card(Rank::Ace, Suit::Heart, true)),
+                Some(card(Rank::Two, Suit::Heart, true)),
+            ]
+        );
+
+        // Taking a move back drops whatever wasn't shown yet.
+        let mut game = GameState::from_table(TEST_SEED, table);
+        game.set_difficulty(Difficulty::Easy);
+        select_source(&mut game, Source::new(StackId::Tableau1, 1));
+        select_target(&mut game, StackId::Tableau2);
+        game.handle_input(Input::Undo);
+        assert_eq!(game.next_auto_play(), None);
+    }
+
+    #[test]
+    fn test_foundations_first_targets() {
+        let foundations = vec![vec![card(Rank::Ace, Suit::Spade, true)]];
+        let tableaux = vec![
+            vec![card(Rank::Three, Suit::Heart, true)],
+            vec![card(Rank::Two, Suit::Spade, true)],
+        ];
+        let table = Table::from_layout(Vec::new(), Vec::new(), foundations, tableaux);
+        let two = Source::new(StackId::Tableau2, 0);
+
+        let mut game = GameState::from_table(TEST_SEED, table.clone());
+        select_source(&mut game, two);
+        assert_eq!(
+            game.targets,
+            vec![StackId::Foundation1, StackId::Tableau1, StackId::Tableau2]
+        );
+        assert_eq!(game.table.target, StackId::Tableau2);
+
+        let mut game = GameState::from_table(TEST_SEED, table);
+        game.set_foundations_first(true);
+        select_source(&mut game, two);
+        assert_eq!(
+            game.targets,
+            vec![StackId::Foundation1, StackId::Tableau2, StackId::Tableau1]
+        );
+        assert_eq!(game.table.target, StackId::Foundation1);
+        game.handle_input(Input::Select);
+        assert_eq!(game.table.foundations[0].len(), 2);
+    }
+
+    #[test]
+    fn test_auto_recycle_on_empty_stock() {
+        let waste = vec![
+            card(Rank::Four, Suit::Club, true),
+            card(Rank::Nine, Suit::Heart, true),
+        ];
+        let tableaux = vec![vec![card(Rank::King, Suit::Spade, true)]];
+        let table = Table::from_layout(Vec::new(), waste, Vec::new(), tableaux);
+
+        for auto_recycle in [false, true] {
+            let mut game = GameState::from_table(TEST_SEED, table.clone());
+            game.set_auto_recycle(auto_recycle);
+            game.handle_input(Input::Next);
+            assert_eq!(game.table.source.stack, StackId::Waste);
+            game.handle_input(Input::Previous);
+            assert_eq!(game.table.source, Source::stock());
+            assert_eq!(game.table.has_cards_in_waste(), !auto_recycle);
+            assert_eq!(game.table.stock.len(), if auto_recycle { 2 } else { 0 });
+
+            game.handle_input(Input::Next);
+            game.handle_input(Input::Previous);
+            let recycles = if auto_recycle { 1 } else { 0 };
+            assert_eq!(game.recording().plays, vec![Play::RecycleWaste; recycles]);
+        }
+    }
+
+    #[test]
+    fn test_tableaux_first_navigation() {
+        let mut game = GameState::new(TEST_SEED);
+        assert_eq!(game.active_cards[0], Source::stock());
+        game.set_navigation(NavigationOrder::TableauxFirst);
+        let stacks: Vec<StackId> = game
+            .active_cards
+            .iter()
+            .map(|source| source.stack)
+            .collect();
+        assert_eq!(stacks[..7], TABLEAUX[..]);
+        assert_eq!(stacks[7..], [StackId::Stock]);
+
+        assert_eq!(game.table.source, Source::stock());
+        game.handle_input(Input::Select);
+        let stacks: Vec<StackId> = game
+            .active_cards
+            .iter()
+            .map(|source| source.stack)
+            .collect();
+        assert_eq!(stacks[7..], [StackId::Stock, StackId::Waste]);
+        game.handle_input(Input::Previous);
+        assert_eq!(game.table.source.stack, StackId::Tableau7);
+
+        // Taking back the draw keeps the order chosen after the deal.
+        game.handle_input(Input::Undo);
+        let stacks: Vec<StackId> = game
+            .active_cards
+            .iter()
+            .map(|source| source.stack)
+            .collect();
+        assert_eq!(stacks[..7], TABLEAUX[..]);
+        assert_eq!(stacks[7..], [StackId::Stock]);
+    }
+
+    #[test]
+    fn test_navigation_without_wrap_stops_at_the_ends() {
+        let mut game = GameState::new(TEST_SEED);
+        game.set_wrap(false);
+        let last = *game.active_cards.last().expect("last");
+        for _ in 0..2 * game.active_cards.len() {
+            game.handle_input(Input::Next);
+        }
+        assert_eq!(game.table.source, last);
+        for _ in 0..2 * game.active_cards.len() {
+            game.handle_input(Input::Previous);
+        }
+        assert_eq!(game.table.source, Source::stock());
+
+        select_source(&mut game, Source::new(StackId::Tableau1, 0));
+        let last = *game.targets.last().expect("last");
+        for _ in 0..2 * game.targets.len() {
+            game.handle_input(Input::Next);
+        }
+        assert_eq!(game.table.target, last);
+    }
+
+    #[test]
+    fn test_navigate_with_only_the_stock() {
+        let stock = vec![card(Rank::Ace, Suit::Spade, false)];
+        let table = Table::from_layout(stock, Vec::new(), Vec::new(), Vec::new());
+        let mut game = GameState::from_table(TEST_SEED, table);
+        assert_eq!(game.active_cards, vec![Source::stock()]);
+        for input in [Input::Next, Input::Previous, Input::Previous, Input::Next] {
+            game.handle_input(input);
+            assert_eq!(game.table.source, Source::stock());
+        }
+
+        game.source_index = 5;
+        game.handle_input(Input::Previous);
+        assert_eq!(game.table.source, Source::stock());
+        game.source_index = 5;
+        game.handle_input(Input::Next);
+        assert_eq!(game.table.source, Source::stock());
+
+        game.active_cards.clear();
+        game.handle_input(Input::Next);
+        assert_eq!(game.table.source, Source::stock());
+    }
+
+    #[test]
+    fn test_cancel_puts_cards_back() {
+        let mut game = GameState::new(TEST_SEED);
+        let source = Source::new(StackId::Tableau3, 2);
+        for _ in 0..game.active_cards.len() {
+            if game.table.source == source {
+                break;
+            }
+            game.handle_input(Input::Next);
+        }
+        let before = game.table.clone();
+        game.handle_input(Input::Select);
+        assert!(game.table.cards_in_hand());
+        game.handle_input(Input::Next);
+
+        game.handle_input(Input::Cancel);
+        // Only the target, pointed at the source on pickup, is left different.
+        assert_eq!(game.table.target, source.stack);
+        game.table.target = before.target;
+        assert_eq!(game.table, before);
+        assert!(!game.table.tableaux[2].get_card(1).expect("card").face_up);
+        assert!(game.recording().plays.is_empty());
+        game.handle_input(Input::Cancel);
+        assert_eq!(game.table, before);
+    }
+
+    #[test]
+    fn test_furthest_progress_survives_undo() {
+        let tableaux = vec![
+            vec![card(Rank::Ace, Suit::Spade, true)],
+            vec![card(Rank::Two, Suit::Spade, true)],
+            vec![card(Rank::Ace, Suit::Club, true)],
+        ];
+        let table = Table::from_layout(Vec::new(), Vec::new(), Vec::new(), tableaux);
+        let mut game = GameState::from_table(TEST_SEED, table);
+        assert_eq!(game.furthest_progress(), 0);
+
+        select_source(&mut game, Source::new(StackId::Tableau1, 0));
+        select_target(&mut game, StackId::Foundation1);
+        let play = Play::MoveCards(Source::new(StackId::Tableau2, 0), StackId::Foundation1);
+        game.apply_play(play).expect("apply_play");
+        assert_eq!(game.furthest_progress(), 2);
+
+        game.handle_input(Input::Undo);
+        game.handle_input(Input::Undo);
+        assert_eq!(game.table.cards_in_foundation(), 0);
+        assert_eq!(game.furthest_progress(), 2);
+    }
+
+    #[test]
+    fn test_dead_end_is_stuck() {
+        let game = GameState::from_table(TEST_SEED, Table::dead_end());
+        assert!(game.is_stuck());
+        assert_eq!(game.result(), None);
+        assert!(!GameState::new(TEST_SEED).is_stuck());
+    }
+
+    #[test]
+    fn test_fewest_face_down_survives_undo() {
+        let mut game = GameState::new(TEST_SEED);
+        let dealt = game.table.count_face_down();
+        assert_eq!(game.fewest_face_down(), dealt);
+        game.handle_input(Input::Select);
+        assert_eq!(game.table.count_face_down(), dealt - 3);
+        game.handle_input(Input::Undo);
+        assert_eq!(game.table.count_face_down(), dealt);
+        assert_eq!(game.fewest_face_down(), dealt - 3);
+    }
+
+    #[test]
+    fn test_replay_matches_recorded_game() {
+        let mut game = GameState::new(TEST_SEED);
+        for turn in 0..200 {
+            for _ in 0..turn % 5 {
+                game.handle_input(Input::Next);
+            }
+            game.handle_input(Input::Select);
+        }
+        if game.table.cards_in_hand() {
+            game.handle_input(Input::Select);
+        }
+        let recording = game.recording().clone();
+        assert!(recording
+            .plays
+            .iter()
+            .any(|play| matches!(play, Play::MoveCards(_, _))));
+
+        let json = serde_json::to_string(&recording).expect("to_string");
+        let recording: Recording = serde_json::from_str(&json).expect("from_str");
+        let mut replay = GameState::new(recording.seed);
+        for play in &recording.plays {
+            replay.apply_play(*play).expect("apply_play");
+        }
+
+        assert_eq!(replay.recording(), game.recording());
+        // Everything a save keeps has to match but where the cursor ended up.
+        let saved = |table: &Table| -> Table {
+            let json = serde_json::to_string(table).expect("to_string");
+            serde_json::from_str(&json).expect("from_str")
+        };
+        let mut replayed = saved(&replay.table);
+        replayed.source = game.table.source;
+        replayed.target = game.table.target;
+        assert_eq!(replayed, saved(&game.table));
+    }
+}
@@ -1,13 +1,25 @@
 extern crate alloc;
 
-use alloc::{fmt, vec::Vec};
+use alloc::{fmt, format, string::String, vec, vec::Vec};
 use anyhow::Error;
-use core::mem;
+use core::{iter, mem, str::FromStr};
 use enum_iterator::IntoEnumIterator;
-use rand::{seq::SliceRandom, SeedableRng};
-use serde::Serialize;
-
-#[derive(Clone, Copy, Debug, Eq, Hash, IntoEnumIterator, Ord, PartialEq, PartialOrd, Serialize)]
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    Hash,
+    IntoEnumIterator,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+)]
 pub enum StackId {
     Stock,
     Waste,
@@ -26,55 +38,103 @@ pub enum StackId {
 }
 
 impl StackId {
-    fn next_impl(&self, wrap: bool) -> Option<Self> {
+    // Each foundation only ever builds the one suit.
+    pub fn foundation_suit(&self) -> Option<Suit> {
         match self {
-            StackId::Stock => Some(StackId::Waste),
-            StackId::Waste => Some(StackId::Foundation1),
-            StackId::Foundation1 => Some(StackId::Foundation2),
-            StackId::Foundation2 => Some(StackId::Foundation3),
-            StackId::Foundation3 => Some(StackId::Foundation4),
-            StackId::Foundation4 => Some(StackId::Tableau1),
-            StackId::Tableau1 => Some(StackId::Tableau2),
-            StackId::Tableau2 => Some(StackId::Tableau3),
-            StackId::Tableau3 => Some(StackId::Tableau4),
-            StackId::Tableau4 => Some(StackId::Tableau5),
-            StackId::Tableau5 => Some(StackId::Tableau6),
-            StackId::Tableau6 => Some(StackId::Tableau7),
-            StackId::Tableau7 => {
-                if wrap {
-                    Some(StackId::Stock)
-                } else {
-                    None
-                }
-            }
-            StackId::Hand => Some(StackId::Hand),
+            StackId::Foundation1 => Some(Suit::Spade),
+            StackId::Foundation2 => Some(Suit::Club),
+            StackId::Foundation3 => Some(Suit::Heart),
+            StackId::Foundation4 => Some(Suit::Diamond),
+            _ => None,
         }
     }
 
     pub fn next_no_wrap(&self) -> Option<Self> {
-        self.next_impl(false)
+        NavigationOrder::Standard.next(*self, false)
     }
 
     pub fn next(&self) -> Self {
-        self.next_impl(true).expect("next")
+        NavigationOrder::Standard.next(*self, true).expect("next")
     }
 
     pub fn previous(&self) -> Self {
+        NavigationOrder::Standard.previous(*self)
+    }
+}
+
+const STANDARD_ORDER: &[StackId] = &[
+    StackId::Stock,
+    StackId::Waste,
+    StackId::Foundation1,
+    StackId::Foundation2,
+    StackId::Foundation3,
+    StackId::Foundation4,
+    StackId::Tableau1,
+    StackId::Tableau2,
+    StackId::Tableau3,
+    StackId::Tableau4,
+    StackId::Tableau5,
+    StackId::Tableau6,
+    StackId::Tableau7,
+];
+
+const TABLEAUX_FIRST_ORDER: &[StackId] = &[
+    StackId::Tableau1,
+    StackId::Tableau2,
+    StackId::Tableau3,
+    StackId::Tableau4,
+    StackId::Tableau5,
+    StackId::Tableau6,
+    StackId::Tableau7,
+    StackId::Stock,
+    StackId::Waste,
+    StackId::Foundation1,
+    StackId::Foundation2,
+    StackId::Foundation3,
+    StackId::Foundation4,
+];
+
+// The sequence the cursor walks through the stacks. The hand is never part
+// of it and stays put.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum NavigationOrder {
+    #[default]
+    Standard,
+    TableauxFirst,
+}
+
+impl NavigationOrder {
+    pub fn stacks(&self) -> &'static [StackId] {
         match self {
-            StackId::Stock => StackId::Tableau7,
-            StackId::Waste => StackId::Stock,
-            StackId::Foundation1 => StackId::Waste,
-            StackId::Foundation2 => StackId::Foundation1,
-            StackId::Foundation3 => StackId::Foundation2,
-            StackId::Foundation4 => StackId::Foundation3,
-            StackId::Tableau1 => StackId::Foundation4,
-            StackId::Tableau2 => StackId::Tableau1,
-            StackId::Tableau3 => StackId::Tableau2,
-            StackId::Tableau4 => StackId::Tableau3,
-            StackId::Tableau5 => StackId::Tableau4,
-            StackId::Tableau6 => StackId::Tableau5,
-            StackId::Tableau7 => StackId::Tableau6,
-            StackId::Hand => StackId::Hand,
+            NavigationOrder::Standard => STANDARD_ORDER,
+            NavigationOrder::TableauxFirst => TABLEAUX_FIRST_ORDER,
+        }
+    }
+
+    pub fn position(&self, stack_id: StackId) -> Option<usize> {
+        self.stacks().iter().position(|stack| *stack == stack_id)
+    }
+
+    pub fn first(&self) -> StackId {
+        self.stacks()[0]
+    }
+
+    pub fn next(&self, stack_id: StackId, wrap: bool) -> Option<StackId> {
+        let stacks = self.stacks();
+        match self.position(stack_id) {
+            Some(index) if index + 1 < stacks.len() => Some(stacks[index + 1]),
+            Some(_) if wrap => Some(stacks[0]),
+            Some(_) => None,
+            None => Some(stack_id),
+        }
+    }
+
+    pub fn previous(&self, stack_id: StackId) -> StackId {
+        let stacks = self.stacks();
+        match self.position(stack_id) {
+            Some(0) => stacks[stacks.len() - 1],
+            Some(index) => stacks[index - 1],
+            None => stack_id,
         }
     }
 }
@@ -96,7 +156,19 @@ pub const TABLEAUX: &[StackId] = &[
     StackId::Tableau7,
 ];
 
-#[derive(Clone, Copy, Debug, Eq, IntoEnumIterator, Ord, PartialEq, PartialOrd, Hash)]
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    IntoEnumIterator,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Hash,
+    Serialize,
+)]
 pub enum StackType {
     Stock,
     Waste,
@@ -111,7 +183,9 @@ pub enum Color {
     Red,
 }
 
-#[derive(Clone, Copy, Eq, Hash, IntoEnumIterator, Ord, PartialEq, PartialOrd)]
+#[derive(
+    Clone, Copy, Deserialize, Eq, Hash, IntoEnumIterator, Ord, PartialEq, PartialOrd, Serialize,
+)]
 pub enum Suit {
     Diamond = 2,
     Club = 1,
@@ -138,11 +212,46 @@ impl Suit {
             Suit::Club | Suit::Spade => Color::Black,
         }
     }
-}
 
-//const SUITS: &[Suit] = &[Suit::Diamond, Suit::Club, Suit::Heart, Suit::Spade];
+    pub fn letter(&self) -> &'static str {
+        match self {
+            Suit::Diamond => "D",
+            Suit::Club => "C",
+            Suit::Heart => "H",
+            Suit::Spade => "S",
+        }
+    }
+
+    // Plain symbols without the emoji selector Debug uses, for the font.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Suit::Diamond => "♦",
+            Suit::Club => "♣",
+            Suit::Heart => "♥",
+            Suit::Spade => "♠",
+        }
+    }
+}
 
-#[derive(Clone, Copy, Debug, Eq, Hash, IntoEnumIterator, Ord, PartialEq, PartialOrd)]
+pub const SUITS: &[Suit] = &[Suit::Diamond, Suit::Club, Suit::Heart, Suit::Spade];
+
+// A smaller practice deck, one suit of each color.
+pub const TWO_SUITS: &[Suit] = &[Suit::Heart, Suit::Spade];
+const TWO_SUIT_TABLEAUX: usize = 5;
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    Hash,
+    IntoEnumIterator,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+)]
 pub enum Rank {
     Ace = 1,
     Two,
@@ -159,6 +268,44 @@ pub enum Rank {
     King,
 }
 
+// Indexed by value - 1.
+const RANKS: [Rank; 13] = [
+    Rank::Ace,
+    Rank::Two,
+    Rank::Three,
+    Rank::Four,
+    Rank::Five,
+    Rank::Six,
+    Rank::Seven,
+    Rank::Eight,
+    Rank::Nine,
+    Rank::Ten,
+    Rank::Jack,
+    Rank::Queen,
+    Rank::King,
+];
+
+impl Rank {
+    pub fn value(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_value(value: u8) -> Option<Rank> {
+        (value as usize)
+            .checked_sub(1)
+            .and_then(|index| RANKS.get(index))
+            .copied()
+    }
+
+    pub fn successor(&self) -> Option<Rank> {
+        Rank::from_value(self.value() + 1)
+    }
+
+    pub fn predecessor(&self) -> Option<Rank> {
+        Rank::from_value(self.value() - 1)
+    }
+}
+
 impl From<Rank> for &'static str {
     fn from(rank: Rank) -> Self {
         let label = match rank {
@@ -180,7 +327,7 @@ impl From<Rank> for &'static str {
     }
 }
 
-#[derive(Clone, Eq, Ord, PartialEq, PartialOrd, Hash)]
+#[derive(Clone, Deserialize, Eq, Ord, PartialEq, PartialOrd, Hash, Serialize)]
 pub struct Card {
     pub suit: Suit,
     pub rank: Rank,
@@ -188,13 +335,26 @@ pub struct Card {
 }
 
 impl Card {
+    pub fn value(&self) -> u8 {
+        self.rank.value()
+    }
+
     pub fn is_same_color(&self, other: &Card) -> bool {
         self.suit.color() == other.suit.color()
     }
 
     pub fn is_one_below(&self, other: &Card) -> bool {
-        let delta = other.rank as i32 - self.rank as i32;
-        delta == 1
+        self.rank.successor() == Some(other.rank)
+    }
+
+    // What the player sees, e.g. "T♠", whichever way up the card is.
+    pub fn label(&self) -> String {
+        let rank: &str = self.rank.into();
+        format!("{}{}", rank, self.suit.symbol())
+    }
+
+    fn key_byte(&self) -> u8 {
+        (self.face_up as u8) << 7 | (self.suit as u8) << 4 | self.rank.value()
     }
 }
 
@@ -206,7 +366,39 @@ impl fmt::Debug for Card {
     }
 }
 
-#[derive(Clone, Hash, PartialEq, Eq)]
+// Plain ASCII, e.g. "TS" face up or "-QH" face down, so boards can be typed.
+impl fmt::Display for Card {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let face_up = if self.face_up { "" } else { "-" };
+        let rank: &str = self.rank.into();
+        f.write_fmt(format_args!("{}{}{}", face_up, rank, self.suit.letter()))
+    }
+}
+
+impl FromStr for Card {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Error> {
+        let (face_up, label) = match text.strip_prefix('-') {
+            Some(label) => (false, label),
+            None => (true, text),
+        };
+        let card = Rank::into_enum_iter()
+            .flat_map(|rank| Suit::into_enum_iter().map(move |suit| (rank, suit)))
+            .find(|(rank, suit)| {
+                let rank: &str = (*rank).into();
+                label.strip_prefix(rank) == Some(suit.letter())
+            })
+            .map(|(rank, suit)| Card {
+                suit,
+                rank,
+                face_up,
+            });
+        card.ok_or_else(|| Error::msg(format!("bad card {:?}", text)))
+    }
+}
+
+#[derive(Clone, Deserialize, Hash, PartialEq, Eq, Serialize)]
 pub struct Stack {
     pub stack_id: StackId,
     pub stack_type: StackType,
@@ -214,27 +406,49 @@ pub struct Stack {
 }
 
 impl Stack {
+    // None for any index past the top, so a caller working from a stale
+    // length, such as a view mid-animation, can't panic.
     pub fn get_card(&self, index: usize) -> Option<&Card> {
         self.cards.get(index)
     }
 
+    // None on an empty stack.
     pub fn get_top_card(&self) -> Option<&Card> {
-        let len = self.len();
-        if len > 0 {
-            self.get_card(len - 1)
-        } else {
-            None
-        }
+        self.cards.last()
     }
 
     pub fn len(&self) -> usize {
         self.cards.len()
     }
 
+    // Debug builds catch a card put where the rules never could, such as
+    // out of order on a foundation.
+    pub fn push_card(&mut self, card: Card) {
+        debug_assert!(
+            self.stack_type != StackType::Foundation || self.foundation_can_accept_card(&card),
+            "{:?} can't go on {:?}",
+            card,
+            self.stack_id
+        );
+        self.cards.push(card);
+    }
+
+    pub fn pop_card(&mut self) -> Option<Card> {
+        self.cards.pop()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.cards.is_empty()
     }
 
+    pub fn iter(&self) -> core::slice::Iter<'_, Card> {
+        self.cards.iter()
+    }
+
+    pub fn face_up_cards(&self) -> impl Iterator<Item = &Card> {
+        self.cards.iter().filter(|card| card.face_up)
+    }
+
     pub fn find_card(&self, rank: Rank, suit: Suit) -> Option<usize> {
         self.cards
             .iter()
@@ -253,25 +467,21 @@ impl Stack {
     }
 
     pub fn bottom_card(&self) -> Option<&Card> {
-        if self.cards.is_empty() {
-            None
-        } else {
-            Some(&self.cards[0])
-        }
+        self.cards.first()
     }
 
     pub fn top_card(&self) -> Option<&Card> {
-        if self.cards.is_empty() {
-            None
-        } else {
-            Some(&self.cards[self.cards.len() - 1])
-        }
+        self.get_top_card()
     }
 
-    pub fn expose_top_card(&mut self) {
-        if !self.cards.is_empty() {
-            let last_index = self.cards.len() - 1;
-            self.cards[last_index].face_up = true;
+    // Whether the top card was face down before.
+    pub fn expose_top_card(&mut self) -> bool {
+        match self.cards.last_mut() {
+            Some(card) if !card.face_up => {
+                card.face_up = true;
+                true
+            }
+            _ => false,
         }
     }
 
@@ -347,16 +557,37 @@ impl Stack {
         return false;
     }
 
+    // How many cards from index up are face up and build down in
+    // alternating colors, stopping at the first that doesn't.
+    pub fn max_movable_run(&self, from: usize) -> usize {
+        let run = match self.cards.get(from..) {
+            Some(run) if run.first().is_some_and(|card| card.face_up) => run,
+            _ => return 0,
+        };
+        1 + run
+            .windows(2)
+            .take_while(|pair| {
+                pair[1].face_up
+                    && !pair[0].is_same_color(&pair[1])
+                    && pair[1].is_one_below(&pair[0])
+            })
+            .count()
+    }
+
+    // Every card from index to the top is face up and builds down in
+    // alternating colors.
+    pub fn is_valid_run(&self, index: usize) -> bool {
+        index < self.cards.len() && self.max_movable_run(index) == self.cards.len() - index
+    }
+
+    pub fn is_run_start(&self, index: usize) -> bool {
+        self.is_valid_run(index) && (index == 0 || !self.is_valid_run(index - 1))
+    }
+
     pub fn foundation_can_accept_card(&self, card: &Card) -> bool {
         if self.cards.is_empty() {
-            if card.rank == Rank::Ace {
-                match self.stack_id {
-                    StackId::Foundation1 => card.suit == Suit::Spade,
-                    StackId::Foundation2 => card.suit == Suit::Club,
-                    StackId::Foundation3 => card.suit == Suit::Heart,
-                    StackId::Foundation4 => card.suit == Suit::Diamond,
-                    _ => false,
-                }
+            if card.rank.predecessor().is_none() {
+                self.stack_id.foundation_suit() == Some(card.suit)
             } else {
                 false
             }
@@ -373,15 +604,21 @@ impl Stack {
         }
     }
 
-    pub fn foundation_can_accept_hand(&self, hand: &Stack) -> bool {
-        if hand.cards.len() > 1 {
-            false
-        } else {
-            if let Some(card) = &hand.top_card() {
-                self.foundation_can_accept_card(card)
-            } else {
-                false
-            }
+    // Foundations are built one card at a time, whatever is doing the moving,
+    // unless the rules let a run of the suit go up together.
+    fn foundation_can_take(moving_cards_count: usize, runs: bool) -> bool {
+        moving_cards_count == 1 || (runs && moving_cards_count > 1)
+    }
+
+    pub fn foundation_can_accept_cards(&self, cards: &[Card], runs: bool) -> bool {
+        if !Self::foundation_can_take(cards.len(), runs) {
+            return false;
+        }
+        match cards.first() {
+            Some(card) if self.foundation_can_accept_card(card) => cards
+                .windows(2)
+                .all(|pair| pair[0].suit == pair[1].suit && pair[0].is_one_below(&pair[1])),
+            _ => false,
         }
     }
 
@@ -393,30 +630,39 @@ impl Stack {
                 false
             }
         } else {
-            card.rank == Rank::King
+            card.rank.successor().is_none()
         }
     }
 
-    pub fn tableau_can_accept_hand(&self, hand: &Stack) -> bool {
-        if let Some(card) = &hand.bottom_card() {
+    pub fn tableau_can_accept_cards(&self, cards: &[Card]) -> bool {
+        if let Some(card) = cards.first() {
             self.tableau_can_accept_card(card)
         } else {
             false
         }
     }
 
-    pub fn can_play(&self, hand: &Stack) -> bool {
+    pub fn can_play(&self, hand: &Stack, options: &TableOptions) -> bool {
+        self.can_play_cards(&hand.cards, options)
+    }
+
+    // The cards are bottom first, as they'd sit in the hand.
+    pub fn can_play_cards(&self, cards: &[Card], options: &TableOptions) -> bool {
         match self.stack_type {
-            StackType::Foundation => self.foundation_can_accept_hand(hand),
-            StackType::Tableau => self.tableau_can_accept_hand(hand),
+            StackType::Foundation => {
+                self.foundation_can_accept_cards(cards, options.foundation_runs)
+            }
+            StackType::Tableau => self.tableau_can_accept_cards(cards),
             _ => false,
         }
     }
 
-    pub fn can_play_card(&self, card: &Card, moving_cards_count: usize) -> bool {
+    // The card is the bottom of what's moving.
+    pub fn can_play_card(&self, card: &Card, moving_cards_count: usize, runs: bool) -> bool {
         match self.stack_type {
             StackType::Foundation => {
-                moving_cards_count == 1 && self.foundation_can_accept_card(card)
+                Self::foundation_can_take(moving_cards_count, runs)
+                    && self.foundation_can_accept_card(card)
             }
             StackType::Tableau => self.tableau_can_accept_card(card),
             _ => false,
@@ -432,6 +678,15 @@ impl Stack {
     }
 }
 
+impl<'a> IntoIterator for &'a Stack {
+    type Item = &'a Card;
+    type IntoIter = core::slice::Iter<'a, Card>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
 impl fmt::Debug for Stack {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         f.write_fmt(format_args!("{:?}: [", self.stack_id))?;
@@ -449,10 +704,58 @@ impl fmt::Debug for Stack {
     }
 }
 
-pub fn make_deck(seed: u64) -> Vec<Card> {
-    let mut rng = rand_pcg::Pcg32::seed_from_u64(seed);
+// Found with the default shuffle, so there's no list with any other.
+#[cfg(not(feature = "xoshiro_shuffle"))]
+pub const WINABLE_SEEDS: &[u64] = &[
+    322, 331, 341, 1004, 1006, 1013, 1016, 1018, 1021, 1023, 1026, 1032, 1038, 1040, 1041, 1042,
+    1044, 1055, 1056, 1058, 1061, 1064, 1079, 1082, 1088, 1093, 1095, 1104, 1113, 1118, 1119, 1120,
+    1125, 1132, 1138, 1145, 1146, 1165, 1172, 1176, 1177, 1178, 1180, 1181, 1191, 1193, 1195, 1203,
+    1207, 1208, 1211, 1215, 1219, 1222, 1225, 1227, 1229, 1231, 1239, 1240, 1244, 1245, 1247, 1248,
+    1249, 1252, 1256, 1265, 1272, 1273, 1274, 1275, 1277, 1278, 1291, 1293, 1295, 1306, 1307, 1308,
+    1312, 1318, 1320, 1329, 1330, 1336, 1341, 1354, 1357, 1360, 1362, 1366, 1367, 1369, 1373, 1378,
+    1379, 1380, 1382, 1385, 1386, 1397, 1409, 1415, 1418, 1428, 1434, 1435, 1441, 1447, 1448, 1451,
+    1455, 1458, 1460, 1463, 1466, 1476, 1477, 1478, 1481, 1497, 1499, 1512, 1515, 1518, 1520, 1527,
+    1532, 1536, 1541, 1542, 1545, 1556, 1557, 1561, 1562, 1573, 1581, 1585, 1592, 1599, 1600, 1602,
+    1616, 1621, 1622, 1623, 1624, 1625, 1627, 1628, 1631, 1632, 1639, 1642, 1653, 1657, 1659, 1660,
+    1668, 1678, 1679, 1682, 1683, 1684, 1694, 1712, 1714, 1731, 1748, 1750, 1753, 1754, 1758, 1762,
+    1764, 1777, 1778, 1791, 1808, 1812, 1813, 1816, 1825, 1846, 1851, 1860, 1864, 1866, 1867, 1869,
+    1872, 1876, 1882, 1884, 1886, 1889, 1891, 1893, 1896, 1901, 1902, 1904, 1906, 1916, 1920, 1921,
+    1922, 1927, 1929, 1934, 1935, 1943, 1944, 1946, 1954, 1955, 1956, 1959, 1968, 1972, 1978, 1987,
+    1990, 1993,
+];
+
+#[cfg(not(feature = "xoshiro_shuffle"))]
+pub fn is_known_winnable(seed: u64) -> bool {
+    WINABLE_SEEDS.binary_search(&seed).is_ok()
+}
+
+// A deal for a new game, known to be winnable where there's a list to
+// choose from.
+#[cfg(not(feature = "xoshiro_shuffle"))]
+pub fn random_seed<R: Rng>(rng: &mut R) -> u64 {
+    *WINABLE_SEEDS.choose(rng).expect("seed")
+}
+
+#[cfg(feature = "xoshiro_shuffle")]
+pub fn random_seed<R: Rng>(rng: &mut R) -> u64 {
+    rng.gen()
+}
+
+// What make_deck shuffles with. WINABLE_SEEDS were all found with Pcg32,
+// so they mean nothing with the xoshiro_shuffle feature on.
+#[cfg(not(feature = "xoshiro_shuffle"))]
+pub type ShuffleRng = rand_pcg::Pcg32;
+#[cfg(feature = "xoshiro_shuffle")]
+pub type ShuffleRng = rand_xoshiro::Xoshiro256PlusPlus;
 
-    let mut cards: Vec<Card> = Suit::into_enum_iter()
+pub fn make_deck(seed: u64, suits: &[Suit]) -> Vec<Card> {
+    make_deck_with(&mut ShuffleRng::seed_from_u64(seed), suits)
+}
+
+pub fn make_deck_with<R: Rng>(rng: &mut R, suits: &[Suit]) -> Vec<Card> {
+    let mut cards: Vec<Card> = suits
+        .iter()
+        .copied()
         .map(move |suit| {
             Rank::into_enum_iter().map(move |rank| Card {
                 suit,
@@ -462,11 +765,11 @@ pub fn make_deck(seed: u64) -> Vec<Card> {
         })
         .flatten()
         .collect();
-    cards.shuffle(&mut rng);
+    cards.shuffle(rng);
     cards
 }
 
-#[derive(Clone, Copy, Eq, Ord, PartialEq, PartialOrd, Hash, Serialize)]
+#[derive(Clone, Copy, Deserialize, Eq, Ord, PartialEq, PartialOrd, Hash, Serialize)]
 pub struct Source {
     pub stack: StackId,
     pub index: usize,
@@ -491,7 +794,90 @@ impl fmt::Debug for Source {
     }
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct TableOptions {
+    // Only whole runs can be picked up from a tableau, never a buried card.
+    pub strict_runs: bool,
+    // A card is only safe to play up once every other suit has caught up,
+    // not just the opposite color.
+    #[serde(default)]
+    pub strict_safety: bool,
+    // One card comes off the stock per draw instead of three.
+    #[serde(default)]
+    pub draw_one: bool,
+    // How many times the waste may go back to the stock; None is no limit.
+    #[serde(default)]
+    pub redeal_limit: Option<u32>,
+    // House rule: a run of one suit, in order, can go up to a foundation
+    // in one move.
+    #[serde(default)]
+    pub foundation_runs: bool,
+    // Cards that are safe to put up go there after every move the player
+    // makes. The game makes those moves; the table only carries the flag.
+    #[serde(default)]
+    pub auto_play: bool,
+    // Vegas scoring: the deal costs the buy-in and every card up pays back.
+    #[serde(default)]
+    pub scoring: bool,
+}
+
+impl TableOptions {
+    pub fn from_difficulty(difficulty: Difficulty) -> Self {
+        let (draw_one, redeal_limit, auto_play, scoring) = match difficulty {
+            Difficulty::Easy => (true, None, true, false),
+            Difficulty::Standard => (false, None, false, false),
+            Difficulty::Vegas => (false, Some(1), false, true),
+        };
+        Self {
+            draw_one,
+            redeal_limit,
+            auto_play,
+            scoring,
+            ..Self::default()
+        }
+    }
+
+    pub fn draw_count(&self) -> usize {
+        if self.draw_one {
+            1
+        } else {
+            3
+        }
+    }
+}
+
+// Presets for the rules that make a deal easier or harder to win.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, IntoEnumIterator, PartialEq, Serialize)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Standard,
+    Vegas,
+}
+
+impl Difficulty {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Standard => "Standard",
+            Difficulty::Vegas => "Vegas",
+        }
+    }
+}
+
+// What a board is assumed to hold when it doesn't say otherwise.
+const DECK_SIZE: usize = 52;
+
+const VEGAS_BUY_IN: i32 = 52;
+const VEGAS_PER_CARD: i32 = 5;
+
+// One byte per card plus one closing each of the stock, waste, hand, four
+// foundations and seven tableaux, and one last for the redeals left.
+pub const CANONICAL_KEY_LEN: usize = 52 + 14 + 1;
+pub type CanonicalKey = [u8; CANONICAL_KEY_LEN];
+const STACK_END: u8 = 0xff;
+
+#[derive(Debug, Clone, Deserialize, Hash, PartialEq, Eq, Serialize)]
 pub struct Table {
     pub stock: Stack,
     pub waste: Stack,
@@ -500,46 +886,135 @@ pub struct Table {
     pub tableaux: Vec<Stack>,
     pub source: Source,
     pub target: StackId,
+    pub options: TableOptions,
+    moves: u32,
+    #[serde(skip)]
+    last_event: Option<GameEvent>,
+    // What the deck was shuffled with, when that's known.
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default)]
+    redeals: u32,
+    // How many cards were dealt, so a board missing a whole suit doesn't
+    // pass for a smaller deck.
+    #[serde(default = "full_deck_size")]
+    deck_size: usize,
+}
+
+fn full_deck_size() -> usize {
+    DECK_SIZE
 }
 
 impl Table {
     pub fn new(seed: u64) -> Self {
-        let mut cards = make_deck(seed);
+        Self::from_deck(seed, SUITS, TABLEAUX.len())
+    }
+
+    // The practice layout: two suits over five tableaux.
+    pub fn new_two_suit(seed: u64) -> Self {
+        Self::from_deck(seed, TWO_SUITS, TWO_SUIT_TABLEAUX)
+    }
+
+    // A seeded deal of just the given suits over the first tableau_count
+    // tableaux.
+    fn from_deck(seed: u64, suits: &[Suit], tableau_count: usize) -> Self {
+        Self {
+            seed: Some(seed),
+            ..Self::deal(make_deck(seed, suits), tableau_count)
+        }
+    }
+
+    pub fn from_rng<R: Rng>(rng: &mut R) -> Self {
+        Self::deal(make_deck_with(rng, SUITS), TABLEAUX.len())
+    }
+
+    // Deals the first tableau_count tableaux from the deck, leaving the rest
+    // empty and the remaining cards in the stock.
+    fn deal(mut cards: Vec<Card>, tableau_count: usize) -> Self {
+        let mut stack_count = 1;
+        let tableaux: Vec<Vec<Card>> = TABLEAUX[..tableau_count]
+            .iter()
+            .map(|_tableau| {
+                let start = cards.len() - stack_count;
+                let mut tableau_cards = cards.split_off(start);
+                if let Some(top_card) = tableau_cards.last_mut() {
+                    top_card.face_up = true;
+                }
+                stack_count += 1;
+                tableau_cards
+            })
+            .collect();
 
+        Self::from_layout(cards, Vec::new(), Vec::new(), tableaux)
+    }
+
+    // A board with nothing left to play, for testing and showing off the
+    // stuck screen; no deal ever produces it. The aces and twos are buried
+    // under black cards that can't build on each other.
+    pub fn dead_end() -> Self {
+        let buried = [
+            (Rank::Ace, Suit::Heart, Rank::Three, Suit::Spade),
+            (Rank::Ace, Suit::Diamond, Rank::Five, Suit::Spade),
+            (Rank::Ace, Suit::Spade, Rank::Seven, Suit::Spade),
+            (Rank::Ace, Suit::Club, Rank::Nine, Suit::Spade),
+            (Rank::Two, Suit::Heart, Rank::Jack, Suit::Spade),
+            (Rank::Two, Suit::Diamond, Rank::Three, Suit::Club),
+            (Rank::Two, Suit::Spade, Rank::Five, Suit::Club),
+        ];
+        let tableaux = buried
+            .iter()
+            .map(|(down_rank, down_suit, up_rank, up_suit)| {
+                let down = Card {
+                    rank: *down_rank,
+                    suit: *down_suit,
+                    face_up: false,
+                };
+                let up = Card {
+                    rank: *up_rank,
+                    suit: *up_suit,
+                    face_up: true,
+                };
+                vec![down, up]
+            })
+            .collect();
+        Self::from_layout(Vec::new(), Vec::new(), Vec::new(), tableaux)
+    }
+
+    pub fn from_layout(
+        stock: Vec<Card>,
+        waste: Vec<Card>,
+        foundations: Vec<Vec<Card>>,
+        tableaux: Vec<Vec<Card>>,
+    ) -> Self {
+        let mut foundations = foundations.into_iter();
         let foundations: Vec<Stack> = FOUNDATIONS
             .iter()
             .map(|foundation| Stack {
                 stack_id: *foundation,
                 stack_type: StackType::Foundation,
-                cards: Vec::new(),
+                cards: foundations.next().unwrap_or_default(),
             })
             .collect();
 
-        let mut stack_count = 1;
+        let mut tableaux = tableaux.into_iter();
         let tableaux: Vec<Stack> = TABLEAUX
             .iter()
-            .map(|tableau| {
-                let start = cards.len() - stack_count;
-                let mut stack = Stack {
-                    stack_id: *tableau,
-                    stack_type: StackType::Tableau,
-                    cards: cards.split_off(start),
-                };
-                stack.flip_top_card();
-                stack_count += 1;
-                stack
+            .map(|tableau| Stack {
+                stack_id: *tableau,
+                stack_type: StackType::Tableau,
+                cards: tableaux.next().unwrap_or_default(),
             })
             .collect();
 
         let stock = Stack {
             stack_id: StackId::Stock,
             stack_type: StackType::Stock,
-            cards: cards,
+            cards: stock,
         };
         let waste = Stack {
             stack_id: StackId::Waste,
             stack_type: StackType::Waste,
-            cards: Vec::new(),
+            cards: waste,
         };
         let in_hand = Stack {
             stack_id: StackId::Hand,
@@ -547,6 +1022,12 @@ impl Table {
             cards: Vec::new(),
         };
         let source_index = stock.next_active_card(None).unwrap_or(0);
+        let deck_size = iter::once(&stock)
+            .chain(iter::once(&waste))
+            .chain(foundations.iter())
+            .chain(tableaux.iter())
+            .map(|stack| stack.cards.len())
+            .sum();
         Self {
             stock,
             waste,
@@ -558,6 +1039,12 @@ impl Table {
                 index: source_index,
             },
             target: StackId::Stock,
+            options: TableOptions::default(),
+            moves: 0,
+            last_event: None,
+            seed: None,
+            redeals: 0,
+            deck_size,
         }
     }
 
@@ -580,6 +1067,18 @@ impl Table {
         }
     }
 
+    // The card a play picks up, the lowest of them when it takes several.
+    pub fn moved_card(&self, play: Play) -> Option<Card> {
+        match play {
+            Play::MoveCards(source, _) => self
+                .get_stack(source.stack)
+                .cards
+                .get(source.index)
+                .cloned(),
+            _ => None,
+        }
+    }
+
     pub fn get_stack_mut(&mut self, stack_type: StackId) -> &mut Stack {
         match stack_type {
             StackId::Stock => &mut self.stock,
@@ -612,8 +1111,12 @@ impl Table {
         None
     }
 
+    pub fn hand_card_count(&self) -> usize {
+        self.in_hand.len()
+    }
+
     pub fn cards_in_hand(&self) -> bool {
-        self.in_hand.cards.len() > 0
+        self.hand_card_count() > 0
     }
 
     pub fn has_cards_in_stock(&self) -> bool {
@@ -624,6 +1127,23 @@ impl Table {
         self.waste.cards.len() > 0
     }
 
+    pub fn can_recycle(&self) -> bool {
+        let redeals_left = self
+            .options
+            .redeal_limit
+            .is_none_or(|limit| self.redeals < limit);
+        !self.has_cards_in_stock() && self.has_cards_in_waste() && redeals_left
+    }
+
+    // Cards still hidden, face down in the tableaux or waiting in the stock.
+    pub fn count_face_down(&self) -> usize {
+        iter::once(&self.stock)
+            .chain(self.tableaux.iter())
+            .flat_map(|stack| stack.cards.iter())
+            .filter(|card| !card.face_up)
+            .count()
+    }
+
     pub fn cards_in_foundation(&self) -> usize {
         self.foundations
             .iter()
@@ -631,11 +1151,96 @@ impl Table {
             .sum::<usize>()
     }
 
+    // Tables also carry the cursor and a move count, which don't change what
+    // can be played from here, so they are left out.
+    pub fn canonical_key(&self) -> CanonicalKey {
+        let stacks = [&self.stock, &self.waste, &self.in_hand];
+        let bytes = stacks
+            .iter()
+            .copied()
+            .chain(self.foundations.iter())
+            .chain(self.tableaux.iter())
+            .flat_map(|stack| {
+                stack
+                    .iter()
+                    .map(Card::key_byte)
+                    .chain(iter::once(STACK_END))
+            });
+        let mut key = [0; CANONICAL_KEY_LEN];
+        for (slot, byte) in key.iter_mut().zip(bytes) {
+            *slot = byte;
+        }
+        // Under a limit the same cards with fewer redeals left can be a
+        // lost position; without one the count doesn't matter.
+        if let Some(limit) = self.options.redeal_limit {
+            let redeals_left = limit.saturating_sub(self.redeals).min(u8::MAX as u32);
+            key[CANONICAL_KEY_LEN - 1] = redeals_left as u8;
+        }
+        key
+    }
+
+    // The derived Eq also compares the cursor, the move count and the last
+    // event; this only asks whether the same cards are in the same places.
+    // canonical_key is the hashable form of the same comparison.
+    pub fn equivalent_ignoring_cursor(&self, other: &Table) -> bool {
+        self.canonical_key() == other.canonical_key()
+    }
+
+    // Measured against the cards actually dealt, so smaller decks finish
+    // sooner.
+    pub fn cards_remaining_to_win(&self) -> usize {
+        self.card_count() - self.cards_in_foundation()
+    }
+
+    // What the most recent deal, recycle or put-down did worth telling the
+    // player about, if anything.
+    pub fn last_event(&self) -> Option<GameEvent> {
+        self.last_event
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn move_count(&self) -> u32 {
+        self.moves
+    }
+
+    pub fn card_count(&self) -> usize {
+        let stacks = [&self.stock, &self.waste, &self.in_hand];
+        stacks
+            .iter()
+            .copied()
+            .chain(self.foundations.iter())
+            .chain(self.tableaux.iter())
+            .map(|stack| stack.cards.len())
+            .sum()
+    }
+
+    // An empty table has nothing left to play but hasn't been won.
     pub fn winner(&self) -> bool {
-        self.cards_in_foundation() == 52
+        self.card_count() > 0 && self.cards_remaining_to_win() == 0
+    }
+
+    // Everything the cursor can select, with the stock, which always can be,
+    // in its place in the navigation order.
+    pub fn active_sources(&self, navigation: NavigationOrder) -> impl Iterator<Item = Source> + '_ {
+        let stock = navigation.position(StackId::Stock);
+        let before_stock = move |source: &Source| navigation.position(source.stack) < stock;
+        ActiveCardIterator::new(self, navigation)
+            .filter(before_stock)
+            .chain(iter::once(Source::stock()))
+            .chain(
+                ActiveCardIterator::new(self, navigation)
+                    .filter(move |source| !before_stock(source)),
+            )
     }
 
-    pub fn next_active_card(&self) -> Option<Source> {
+    fn next_stack(&self, stack_id: StackId, navigation: NavigationOrder) -> StackId {
+        navigation.next(stack_id, true).expect("next")
+    }
+
+    pub fn next_active_card(&self, navigation: NavigationOrder) -> Option<Source> {
         let mut source = self.source;
         let mut start = Some(source.index);
         loop {
@@ -647,13 +1252,13 @@ impl Table {
                     index: next_index.unwrap(),
                 });
             } else {
-                source.stack = source.stack.next();
+                source.stack = self.next_stack(source.stack, navigation);
                 start = None;
             }
         }
     }
 
-    pub fn previous_active_card(&self) -> Option<Source> {
+    pub fn previous_active_card(&self, navigation: NavigationOrder) -> Option<Source> {
         let mut source = self.source;
         let mut start = Some(source.index);
         loop {
@@ -665,21 +1270,21 @@ impl Table {
                     index: previous_index.unwrap(),
                 });
             } else {
-                source.stack = source.stack.previous();
+                source.stack = navigation.previous(source.stack);
                 start = None;
             }
         }
     }
 
-    pub fn next_play_location(&self) -> StackId {
+    pub fn next_play_location(&self, navigation: NavigationOrder) -> StackId {
         let orginal_stack = self.target;
-        let mut target = orginal_stack.next();
+        let mut target = self.next_stack(orginal_stack, navigation);
         loop {
             let target_stack = self.get_stack(target);
-            if target_stack.can_play(&self.in_hand) {
+            if target_stack.can_play(&self.in_hand, &self.options) {
                 break;
             } else {
-                target = target.next();
+                target = self.next_stack(target, navigation);
             }
             if target == self.source.stack {
                 break;
@@ -688,15 +1293,15 @@ impl Table {
         target
     }
 
-    pub fn previous_play_location(&self) -> StackId {
+    pub fn previous_play_location(&self, navigation: NavigationOrder) -> StackId {
         let orginal_stack = self.target;
-        let mut target = orginal_stack.previous();
+        let mut target = navigation.previous(orginal_stack);
         loop {
             let target_stack = self.get_stack(target);
-            if target_stack.can_play(&self.in_hand) {
+            if target_stack.can_play(&self.in_hand, &self.options) {
                 break;
             } else {
-                target = target.previous();
+                target = navigation.previous(target);
             }
             if target == self.source.stack {
                 break;
@@ -706,24 +1311,56 @@ impl Table {
     }
 
     pub fn deal_from_stock(&mut self) {
-        let amount_to_deal = 3.min(self.stock.cards.len());
-        if amount_to_deal == 0 {
-            mem::swap(&mut self.waste.cards, &mut self.stock.cards);
-            for mut card in &mut self.stock.cards {
-                card.face_up = false;
-            }
-            self.stock.cards.reverse();
-        } else {
-            for _ in 0..amount_to_deal {
-                let mut dealt_card = self.stock.cards.pop().expect("card");
-                dealt_card.face_up = true;
-                self.waste.cards.push(dealt_card);
-            }
+        self.deal_specific(self.options.draw_count());
+    }
+
+    // Deals up to count cards, or turns the waste over once the stock has
+    // run out. Every way of drawing goes through here.
+    pub fn deal_specific(&mut self, count: usize) {
+        if !self.has_cards_in_stock() {
+            self.recycle_waste();
+            return;
+        }
+        self.last_event = None;
+        let amount_to_deal = count.min(self.stock.cards.len());
+        for _ in 0..amount_to_deal {
+            let mut dealt_card = self.stock.pop_card().expect("card");
+            dealt_card.face_up = true;
+            self.waste.push_card(dealt_card);
+        }
+        if amount_to_deal > 0 {
+            self.moves += 1;
         }
     }
 
     pub fn recycle_waste(&mut self) {
-        self.deal_from_stock();
+        // Dealt cards came off the top of the stock, so they go back on top
+        // in the opposite order to how they landed on the waste.
+        if !self.can_recycle() {
+            self.last_event = None;
+            return;
+        }
+        let mut cards = mem::take(&mut self.waste.cards);
+        self.moves += 1;
+        self.redeals += 1;
+        self.last_event = Some(GameEvent::Recycled);
+        cards.reverse();
+        for card in &mut cards {
+            card.face_up = false;
+        }
+        self.stock.cards.append(&mut cards);
+    }
+
+    // Turns every tableau card up, for drawing only. Face-up is also what
+    // makes a card legal to pick up, so this is never done to the table in
+    // play, only to a copy of it that's thrown away once drawn; there is
+    // nothing to turn back down.
+    pub fn reveal_all(&mut self) {
+        for tableau in &mut self.tableaux {
+            for card in &mut tableau.cards {
+                card.face_up = true;
+            }
+        }
     }
 
     pub fn expose_top_card_of_stack(&mut self, stack_id: StackId) {
@@ -731,68 +1368,549 @@ impl Table {
         stack.expose_top_card();
     }
 
-    pub fn take_top_card_from_stack(&mut self, stack_id: StackId) {
+    // Turns up any face-down tableau top. The source of a run in hand is
+    // left alone, since putting the run back must not reveal anything.
+    pub fn normalize(&mut self) {
+        let lifted_from = if self.cards_in_hand() {
+            Some(self.source.stack)
+        } else {
+            None
+        };
+        for tableau in &mut self.tableaux {
+            if Some(tableau.stack_id) != lifted_from && tableau.expose_top_card() {
+                self.last_event = Some(GameEvent::CardFlipped);
+            }
+        }
+    }
+
+    pub fn take_top_card_from_stack(&mut self, stack_id: StackId) -> Result<(), MoveError> {
         let stack = self.get_stack_mut(stack_id);
-        let count = stack.cards.len();
-        if count > 0 {
-            let last_index = count - 1;
-            let mut card = stack.cards.remove(last_index);
-            card.face_up = true;
-            self.in_hand.cards.push(card);
+        let mut card = stack.pop_card().ok_or(MoveError::EmptyStack)?;
+        card.face_up = true;
+        self.in_hand.push_card(card);
+        Ok(())
+    }
+
+    // Whether the cards from source up can be picked up. Nothing is ever
+    // picked up off the stock; selecting it draws instead.
+    pub fn can_select(&self, source: Source) -> bool {
+        if source.stack == StackId::Stock {
+            return false;
+        }
+        let stack = self.get_stack(source.stack);
+        let face_up = stack
+            .get_card(source.index)
+            .map(|card| card.face_up)
+            .unwrap_or(false);
+        if self.options.strict_runs && stack.stack_type == StackType::Tableau {
+            face_up && stack.is_run_start(source.index)
+        } else {
+            face_up
         }
     }
 
-    pub fn take_selected_cards_from_stack(&mut self, stack_id: StackId, index: usize) {
-        let cards_for_hand = {
-            let stack = self.get_stack_mut(stack_id);
-            stack.cards.split_off(index)
-        };
-        let count = cards_for_hand.len();
-        if count > 0 {
-            self.in_hand.cards = cards_for_hand;
+    pub fn take_selected_cards_from_stack(
+        &mut self,
+        stack_id: StackId,
+        index: usize,
+    ) -> Result<(), MoveError> {
+        let card = self
+            .get_stack(stack_id)
+            .get_card(index)
+            .ok_or(MoveError::EmptyStack)?;
+        if !self.can_select(Source::new(stack_id, index)) {
+            return Err(if card.face_up {
+                MoveError::InvalidSequence
+            } else {
+                MoveError::FaceDownCard
+            });
         }
+        let stack = self.get_stack_mut(stack_id);
+        self.in_hand.cards = stack.cards.split_off(index);
+        Ok(())
     }
 
-    pub fn put_hand_on_stack(&mut self, source: Source, stack_id: StackId) -> usize {
-        let mut cards = Vec::new();
-        mem::swap(&mut cards, &mut self.in_hand.cards);
+    pub fn put_hand_on_stack(
+        &mut self,
+        source: Source,
+        stack_id: StackId,
+    ) -> Result<usize, MoveError> {
+        if self.in_hand.is_empty() {
+            return Err(MoveError::EmptyStack);
+        }
+        let target = self.get_stack(stack_id);
+        if source.stack != stack_id && !target.can_play(&self.in_hand, &self.options) {
+            return Err(MoveError::IllegalTarget);
+        }
+        self.last_event = None;
+        let cards = mem::take(&mut self.in_hand.cards);
         let target_stack = self.get_stack_mut(stack_id);
-        let index = target_stack.cards.len();
-        target_stack.cards.append(&mut cards);
-        self.expose_top_card_of_stack(source.stack);
-        index
+        let index = target_stack.len();
+        for card in cards {
+            target_stack.push_card(card);
+        }
+        // Putting cards back where they came from isn't a move.
+        if source.stack != stack_id {
+            self.moves += 1;
+        }
+        self.normalize();
+        // Completing a suit is the bigger news when the same move also
+        // turned a card up.
+        let target_stack = self.get_stack(stack_id);
+        if target_stack.stack_type == StackType::Foundation
+            && target_stack.len() == Rank::King.value() as usize
+        {
+            if let Some(card) = target_stack.bottom_card() {
+                self.last_event = Some(GameEvent::FoundationCompleted(card.suit));
+            }
+        }
+        Ok(index)
     }
 
-    pub fn put_hand_on_target(&mut self) {
+    pub fn put_hand_on_target(&mut self) -> Result<(), MoveError> {
         let target = self.target;
-        let index = self.put_hand_on_stack(self.source, target);
+        let index = self.put_hand_on_stack(self.source, target)?;
         self.source = Source {
             stack: target,
             index: index,
         };
+        Ok(())
+    }
+
+    pub fn apply_play(&mut self, play: Play) -> Result<(), MoveError> {
+        match play {
+            Play::DrawFromStock if !self.has_cards_in_stock() => return Err(MoveError::EmptyStack),
+            Play::DrawFromStock => self.deal_from_stock(),
+            Play::RecycleWaste if !self.can_recycle() => return Err(MoveError::EmptyStack),
+            Play::RecycleWaste => self.recycle_waste(),
+            Play::MoveCards(source, _) if source.stack == StackId::Stock => {
+                return Err(MoveError::FaceDownCard)
+            }
+            Play::MoveCards(source, stack_id) => {
+                self.take_selected_cards_from_stack(source.stack, source.index)?;
+                if let Err(error) = self.put_hand_on_stack(source, stack_id) {
+                    self.put_hand_on_stack(source, source.stack)?;
+                    return Err(error);
+                }
+            }
+            Play::Setup => (),
+        }
+        Ok(())
+    }
+
+    // Like apply_play, but returns what unapply needs to put the table back
+    // exactly as it was, so a search can walk one table instead of cloning.
+    pub fn apply(&mut self, play: Play) -> Result<UndoToken, MoveError> {
+        let mut token = UndoToken {
+            play,
+            moves: self.moves,
+            redeals: self.redeals,
+            last_event: self.last_event,
+            count: 0,
+            flipped: false,
+        };
+        match play {
+            Play::DrawFromStock => token.count = self.options.draw_count().min(self.stock.len()),
+            Play::RecycleWaste => token.count = self.waste.len(),
+            Play::MoveCards(source, _) => {
+                let stack = self.get_stack(source.stack);
+                token.count = stack.len().saturating_sub(source.index);
+                token.flipped = stack.stack_type == StackType::Tableau
+                    && source
+                        .index
+                        .checked_sub(1)
+                        .and_then(|index| stack.get_card(index))
+                        .is_some_and(|card| !card.face_up);
+            }
+            Play::Setup => (),
+        }
+        self.apply_play(play)?;
+        Ok(token)
+    }
+
+    // Only valid for the most recent apply still standing.
+    pub fn unapply(&mut self, token: UndoToken) {
+        match token.play {
+            Play::DrawFromStock => {
+                for _ in 0..token.count {
+                    let mut card = self.waste.pop_card().expect("card");
+                    card.face_up = false;
+                    self.stock.push_card(card);
+                }
+            }
+            Play::RecycleWaste => {
+                // Dealt cards are always face up.
+                let start = self.stock.len() - token.count;
+                let mut cards = self.stock.cards.split_off(start);
+                cards.reverse();
+                for card in &mut cards {
+                    card.face_up = true;
+                }
+                self.waste.cards.append(&mut cards);
+            }
+            Play::MoveCards(source, target) => {
+                let target_stack = self.get_stack_mut(target);
+                let start = target_stack.len() - token.count;
+                let mut cards = target_stack.cards.split_off(start);
+                let source_stack = self.get_stack_mut(source.stack);
+                if token.flipped {
+                    if let Some(card) = source_stack.cards.last_mut() {
+                        card.face_up = false;
+                    }
+                }
+                source_stack.cards.append(&mut cards);
+            }
+            Play::Setup => (),
+        }
+        self.moves = token.moves;
+        self.redeals = token.redeals;
+        self.last_event = token.last_event;
+    }
+
+    // Every play the rules allow from here, the stock first.
+    pub fn legal_plays(&self) -> impl Iterator<Item = Play> + '_ {
+        let stock_play = if self.has_cards_in_stock() {
+            Some(Play::DrawFromStock)
+        } else if self.can_recycle() {
+            Some(Play::RecycleWaste)
+        } else {
+            None
+        };
+        stock_play.into_iter().chain(
+            ActiveCardIterator::new(self, NavigationOrder::Standard)
+                .flat_map(move |source| CardPlayIterator::new(self, source)),
+        )
+    }
+
+    // False once only giving up or undo is left.
+    pub fn has_any_legal_move(&self) -> bool {
+        self.legal_plays().next().is_some()
     }
 
-    pub fn go_next(&mut self) -> Result<(), Error> {
+    // Plays at random until the game is won, stuck or max_moves in, checking
+    // the deck after every play. Returns how many plays were made, or the
+    // first play that failed or broke the deck.
+    pub fn play_random_game<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        max_moves: usize,
+    ) -> Result<usize, Error> {
+        for count in 0..max_moves {
+            if self.winner() {
+                return Ok(count);
+            }
+            let plays: Vec<Play> = self.legal_plays().collect();
+            let play = match plays.choose(rng) {
+                Some(play) => *play,
+                None => return Ok(count),
+            };
+            self.apply_play(play)
+                .map_err(|error| Error::msg(format!("{} failed: {:?}", play, error)))?;
+            self.check_deck()
+                .map_err(|error| Error::msg(format!("{} broke the deck: {}", play, error)))?;
+        }
+        Ok(max_moves)
+    }
+
+    // Tops of the waste and tableaux that can go up to a foundation.
+    fn foundation_plays(&self) -> impl Iterator<Item = (&Card, Play)> + '_ {
+        iter::once(StackId::Waste)
+            .chain(TABLEAUX.iter().copied())
+            .filter_map(move |stack_id| {
+                let stack = self.get_stack(stack_id);
+                let card = stack.get_top_card()?;
+                FOUNDATIONS
+                    .iter()
+                    .find(|foundation| {
+                        self.get_stack(**foundation)
+                            .foundation_can_accept_card(card)
+                    })
+                    .map(|foundation| {
+                        let source = Source::new(stack_id, stack.top_card_index());
+                        (card, Play::MoveCards(source, *foundation))
+                    })
+            })
+    }
+
+    // The foundation the suit has been started on, if its ace is up yet.
+    pub fn foundation_for_suit(&self, suit: Suit) -> Option<StackId> {
+        self.foundations
+            .iter()
+            .find(|foundation| foundation.bottom_card().map(|card| card.suit) == Some(suit))
+            .map(|foundation| foundation.stack_id)
+    }
+
+    // How far up the foundations a suit has been built, counting suits
+    // that were never dealt as complete.
+    fn foundation_rank(&self, suit: Suit) -> u8 {
+        let built = self
+            .foundation_for_suit(suit)
+            .map_or(0, |stack_id| self.get_stack(stack_id).len() as u8);
+        if built == 0 && self.find_card(Rank::Ace, suit).is_none() {
+            Rank::King.value()
+        } else {
+            built
+        }
+    }
+
+    // Nothing left in play could still need this card to build on: both
+    // foundations of the other color already hold the rank below it.
+    pub fn is_safe_to_foundation(&self, card: &Card) -> bool {
+        let value = card.value();
+        if value <= Rank::Two.value() {
+            return true;
+        }
+        SUITS
+            .iter()
+            .filter(|suit| **suit != card.suit)
+            .filter(|suit| self.options.strict_safety || suit.color() != card.suit.color())
+            .all(|suit| self.foundation_rank(*suit) + 1 >= value)
+    }
+
+    // Rough progress for hints: cards home count double the tableau cards
+    // turned up, so burying a face-down card to free one never pays.
+    fn hint_score(&self) -> i32 {
+        let hidden = self.count_face_down() - self.stock.len();
+        2 * self.cards_in_foundation() as i32 - hidden as i32
+    }
+
+    // The first plays of the best line a small search finds within depth
+    // plays; shorter, or empty, when nothing further improves the table.
+    pub fn hint_chain(&self, depth: usize) -> Vec<Play> {
+        let mut table = self.clone();
+        let mut search = HintSearch {
+            nodes: 0,
+            path: Vec::new(),
+            keys: vec![table.canonical_key()],
+            best: Vec::new(),
+            best_score: table.hint_score(),
+        };
+        search.visit(&mut table, depth);
+        search.best
+    }
+
+    // The single play a hint points at, even when it only pays off later.
+    pub fn suggest_move(&self) -> Option<Play> {
+        self.hint_chain(HINT_DEPTH).first().copied()
+    }
+
+    // The lowest card that can go up to a foundation, or else a turn of the
+    // stock to dig for one.
+    pub fn next_auto_complete_play(&self) -> Option<Play> {
+        if self.winner() {
+            return None;
+        }
+        let lowest = self
+            .foundation_plays()
+            .min_by_key(|(card, _)| card.rank)
+            .map(|(_, play)| play);
+        lowest.or_else(|| {
+            if self.has_cards_in_stock() {
+                Some(Play::DrawFromStock)
+            } else if self.can_recycle() {
+                Some(Play::RecycleWaste)
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn auto_complete(&mut self) -> bool {
+        let mut deals_without_move = 0;
+        while let Some(play) = self.next_auto_complete_play() {
+            if let Play::MoveCards(_, _) = play {
+                deals_without_move = 0;
+            } else {
+                // A whole pass through the stock without a move means the
+                // rest of the waste can never come out.
+                deals_without_move += 1;
+                if deals_without_move > self.stock.len() + self.waste.len() + 1 {
+                    return false;
+                }
+            }
+            if self.apply_play(play).is_err() {
+                return false;
+            }
+        }
+        self.winner()
+    }
+
+    pub fn next_safe_foundation_play(&self) -> Option<Play> {
+        self.foundation_plays()
+            .find(|(card, _)| self.is_safe_to_foundation(card))
+            .map(|(_, play)| play)
+    }
+
+    // Plays every card that is safe to put up, returning how many went.
+    pub fn auto_play_to_foundations(&mut self) -> usize {
+        let mut played = 0;
+        loop {
+            match self.next_safe_foundation_play() {
+                Some(play) if self.apply_play(play).is_ok() => played += 1,
+                _ => return played,
+            }
+        }
+    }
+
+    // In dollars, so a deal starts at minus the buy-in. A card taken back
+    // down off a foundation gives up what it paid.
+    pub fn vegas_score(&self) -> i32 {
+        VEGAS_PER_CARD * self.cards_in_foundation() as i32 - VEGAS_BUY_IN
+    }
+
+    pub fn can_auto_complete(&self) -> bool {
+        !self.cards_in_hand()
+            && self
+                .tableaux
+                .iter()
+                .all(|tableau| tableau.cards.iter().all(|card| card.face_up))
+            && self.clone().auto_complete()
+    }
+
+    pub fn go_next(&mut self, navigation: NavigationOrder) -> Result<(), Error> {
         if self.cards_in_hand() {
-            self.target = self.next_play_location();
+            self.target = self.next_play_location(navigation);
         } else {
-            self.source = self.next_active_card().unwrap_or_else(|| Source::stock())
+            self.source = self
+                .next_active_card(navigation)
+                .unwrap_or_else(Source::stock);
+            debug_assert!(
+                self.source == Source::stock() || self.can_select(self.source),
+                "{:?}",
+                self.source
+            );
         }
         Ok(())
     }
 
+    // Whether picking up everything from source onward and dropping it on
+    // target would be legal.
+    pub fn can_move(&self, source: Source, target: StackId) -> bool {
+        if source.stack == target {
+            return false;
+        }
+        let source_stack = self.get_stack(source.stack);
+        match source_stack.get_card(source.index) {
+            Some(card) if card.face_up && self.can_select(source) => (),
+            _ => return false,
+        }
+        self.get_stack(target)
+            .can_play_cards(&source_stack.cards[source.index..], &self.options)
+    }
+
     pub fn stack_can_accept_hand(&self, stack_id: StackId) -> bool {
         let target = self.get_stack(stack_id);
-        target.can_play(self.get_stack(StackId::Hand))
+        target.can_play(self.get_stack(StackId::Hand), &self.options)
     }
 
-    pub fn go_previous(&mut self) -> Result<(), Error> {
+    pub fn go_previous(&mut self, navigation: NavigationOrder) -> Result<(), Error> {
         if self.cards_in_hand() {
-            self.target = self.previous_play_location();
+            self.target = self.previous_play_location(navigation);
         } else {
             self.source = self
-                .previous_active_card()
+                .previous_active_card(navigation)
                 .unwrap_or_else(|| Source::stock());
+            debug_assert!(
+                self.source == Source::stock() || self.can_select(self.source),
+                "{:?}",
+                self.source
+            );
+        }
+        Ok(())
+    }
+}
+
+// One line per stack, e.g. "Tableau2: -8D 5D", after a "Deck: 26" line
+// for anything short of a full deck. The hand isn't included.
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        if self.deck_size != DECK_SIZE {
+            f.write_fmt(format_args!("Deck: {}\n", self.deck_size))?;
+        }
+        let stacks = iter::once(&self.stock)
+            .chain(iter::once(&self.waste))
+            .chain(self.foundations.iter())
+            .chain(self.tableaux.iter());
+        for stack in stacks {
+            f.write_fmt(format_args!("{:?}:", stack.stack_id))?;
+            for card in &stack.cards {
+                f.write_fmt(format_args!(" {}", card))?;
+            }
+            f.write_str("\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Table {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Error> {
+        let mut table = Table::from_layout(Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        table.deck_size = DECK_SIZE;
+        // Lines starting with # are notes for whoever reads the board.
+        let lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        for line in lines {
+            let (name, cards) = line
+                .split_once(':')
+                .ok_or_else(|| Error::msg(format!("expected a stack name in {:?}", line)))?;
+            if name.trim() == "Deck" {
+                table.deck_size = cards
+                    .trim()
+                    .parse()
+                    .map_err(|_| Error::msg(format!("bad deck size in {:?}", line)))?;
+                continue;
+            }
+            let stack_id = StackId::into_enum_iter()
+                .filter(|stack_id| *stack_id != StackId::Hand)
+                .find(|stack_id| format!("{:?}", stack_id) == name.trim())
+                .ok_or_else(|| Error::msg(format!("unknown stack {:?}", name)))?;
+            let cards = cards
+                .split_whitespace()
+                .map(Card::from_str)
+                .collect::<Result<Vec<Card>, Error>>()?;
+            table.get_stack_mut(stack_id).cards = cards;
+        }
+        table.source.index = table.stock.next_active_card(None).unwrap_or(0);
+        table.check_deck()?;
+        Ok(table)
+    }
+}
+
+impl Table {
+    // Every card that was dealt still here exactly once, in or out of the
+    // hand, a whole suit at a time.
+    pub fn check_deck(&self) -> Result<(), Error> {
+        let mut cards: Vec<&Card> = iter::once(&self.stock)
+            .chain(iter::once(&self.waste))
+            .chain(iter::once(&self.in_hand))
+            .chain(self.foundations.iter())
+            .chain(self.tableaux.iter())
+            .flat_map(|stack| stack.cards.iter())
+            .collect();
+        cards.sort_by_key(|card| (card.suit, card.rank));
+        for pair in cards.windows(2) {
+            if (pair[0].suit, pair[0].rank) == (pair[1].suit, pair[1].rank) {
+                return Err(Error::msg(format!("{} appears more than once", pair[1])));
+            }
+        }
+        if cards.is_empty() {
+            return Err(Error::msg("no cards"));
+        }
+        if cards.len() != self.deck_size {
+            let message = format!("expected {} cards, found {}", self.deck_size, cards.len());
+            return Err(Error::msg(message));
+        }
+        let ranks = Rank::into_enum_iter().count();
+        for suit in SUITS {
+            let count = cards.iter().filter(|card| card.suit == *suit).count();
+            if count != 0 && count != ranks {
+                let message = format!("expected {} {:?}s, found {}", ranks, suit, count);
+                return Err(Error::msg(message));
+            }
         }
         Ok(())
     }
@@ -800,31 +1918,35 @@ impl Table {
 
 pub struct ActiveCardIterator<'a> {
     table: &'a Table,
+    navigation: NavigationOrder,
     source: Option<Source>,
 }
 
 impl<'a> ActiveCardIterator<'a> {
-    pub fn new(table: &'a Table) -> Self {
-        let stacks = StackId::into_enum_iter();
+    pub fn new(table: &'a Table, navigation: NavigationOrder) -> Self {
+        let stacks = navigation.stacks().iter().copied();
         let source = stacks
             .filter_map(|stack_id| {
                 let stack = table.get_stack(stack_id);
-                let active_index = stack.next_active_card(None);
-                if active_index.is_some()
-                    && (stack_id == StackId::Waste
-                        || stack.is_top_face_up_card(active_index.unwrap()))
-                {
-                    Some(Source {
-                        stack: stack_id,
-                        index: active_index.unwrap(),
-                    })
-                } else {
-                    None
+                let mut active_index = stack.next_active_card(None);
+                // Under strict runs the lowest face up card can sit partway
+                // down a run, so step on up the stack as next() does.
+                while let Some(index) = active_index {
+                    let source = Source::new(stack_id, index);
+                    if table.can_select(source) {
+                        return Some(source);
+                    }
+                    active_index = stack.next_active_card(active_index);
                 }
+                None
             })
             .nth(0);
 
-        Self { table, source }
+        Self {
+            table,
+            navigation,
+            source,
+        }
     }
 }
 
@@ -843,13 +1965,21 @@ impl<'a> Iterator for ActiveCardIterator<'a> {
                         stack: source.stack,
                         index: next_index.unwrap(),
                     };
+                    if !self.table.can_select(source) {
+                        // Under strict runs only the start of a run is offered.
+                        start = next_index;
+                        continue;
+                    }
+                    debug_assert!(self.table.can_select(source), "{:?}", source);
                     self.source = Some(source);
                     break;
                 } else {
-                    source.stack = source.stack.next();
-                    if source.stack == StackId::Stock {
-                        self.source = None;
-                        break;
+                    match self.navigation.next(source.stack, false) {
+                        Some(stack_id) => source.stack = stack_id,
+                        None => {
+                            self.source = None;
+                            break;
+                        }
                     }
                     start = None;
                 }
@@ -859,7 +1989,7 @@ impl<'a> Iterator for ActiveCardIterator<'a> {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Serialize)]
 pub enum Play {
     Setup,
     DrawFromStock,
@@ -867,39 +1997,128 @@ pub enum Play {
     MoveCards(Source, StackId),
 }
 
+// One stable line per play, e.g. "MOVE Tableau3[5] -> Foundation2".
+impl fmt::Display for Play {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Play::Setup => f.write_str("SETUP"),
+            Play::DrawFromStock => f.write_str("DRAW"),
+            Play::RecycleWaste => f.write_str("RECYCLE"),
+            Play::MoveCards(source, target) => {
+                f.write_fmt(format_args!("MOVE {:?} -> {:?}", source, target))
+            }
+        }
+    }
+}
+
+// Enough for a hint on a button press.
+const HINT_NODE_LIMIT: usize = 2_000;
+// How many plays ahead a hint looks. suggest_move searches just as far, so
+// it always names the first play of the chain.
+pub const HINT_DEPTH: usize = 4;
+
+// A depth-first walk of one table, never going back to a position already
+// on the current line.
+struct HintSearch {
+    nodes: usize,
+    path: Vec<Play>,
+    keys: Vec<CanonicalKey>,
+    best: Vec<Play>,
+    best_score: i32,
+}
+
+impl HintSearch {
+    fn visit(&mut self, table: &mut Table, depth: usize) {
+        if depth == 0 {
+            return;
+        }
+        // Foundation plays first, so the node limit cuts off the long shots.
+        let mut plays: Vec<Play> = table.legal_plays().collect();
+        plays.sort_by_key(|play| match play {
+            Play::MoveCards(_, target) => {
+                table.get_stack(*target).stack_type != StackType::Foundation
+            }
+            _ => true,
+        });
+        for play in plays {
+            if self.nodes >= HINT_NODE_LIMIT {
+                return;
+            }
+            self.nodes += 1;
+            let token = match table.apply(play) {
+                Ok(token) => token,
+                Err(_) => continue,
+            };
+            let key = table.canonical_key();
+            if !self.keys.contains(&key) {
+                self.path.push(play);
+                self.keys.push(key);
+                let score = table.hint_score();
+                if score > self.best_score {
+                    self.best_score = score;
+                    self.best = self.path.clone();
+                }
+                self.visit(table, depth - 1);
+                self.keys.pop();
+                self.path.pop();
+            }
+            table.unapply(token);
+        }
+    }
+}
+
+// Enough about an applied play to take it back.
+#[derive(Debug, Clone, Copy)]
+pub struct UndoToken {
+    play: Play,
+    moves: u32,
+    redeals: u32,
+    last_event: Option<GameEvent>,
+    // Cards dealt, recycled or moved.
+    count: usize,
+    // Whether the move turned up the card it left on top of a tableau.
+    flipped: bool,
+}
+
+// Things a move did that sound, haptics or animation might want to react to.
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
+pub enum GameEvent {
+    FoundationCompleted(Suit),
+    CardFlipped,
+    Recycled,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MoveError {
+    EmptyStack,
+    IllegalTarget,
+    FaceDownCard,
+    InvalidSequence,
+}
+
 #[derive(Debug)]
 pub struct CardPlayIterator<'a> {
     table: &'a Table,
-    card: &'a Card,
     source: Source,
     play: Option<Play>,
 }
 
 impl<'a> CardPlayIterator<'a> {
-    pub fn new(table: &'a Table, card: &'a Card, source: Source) -> Self {
-        let play = Self::next_legal_play(table, card, source, StackId::Waste);
+    pub fn new(table: &'a Table, source: Source) -> Self {
+        let play = Self::next_legal_play(table, source, StackId::Waste);
         Self {
             table,
-            card,
             source,
             play,
         }
     }
 
-    pub fn next_legal_play(
-        table: &'a Table,
-        card: &'a Card,
-        source: Source,
-        start: StackId,
-    ) -> Option<Play> {
+    pub fn next_legal_play(table: &'a Table, source: Source, start: StackId) -> Option<Play> {
         let mut target = Some(start);
         loop {
             if let Some(current_target) = target {
-                let stack = table.get_stack(current_target);
-                let source_stack = table.get_stack(source.stack);
-                let moving_cards_count = source_stack.cards.len() - source.index;
-                assert!(moving_cards_count > 0);
-                if stack.can_play_card(card, moving_cards_count) {
+                assert!(source.index < table.get_stack(source.stack).len());
+                if table.can_move(source, current_target) {
                     return Some(Play::MoveCards(source, current_target));
                 }
                 target = current_target.next_no_wrap();
@@ -930,7 +2149,6 @@ impl<'a> Iterator for CardPlayIterator<'a> {
                                     if let Some(next_target) = next_target {
                                         self.play = Self::next_legal_play(
                                             self.table,
-                                            self.card,
                                             self.source,
                                             next_target,
                                         );
@@ -0,0 +1,213 @@
+use crate::klondike::FOUNDATIONS;
+use crankstart::{
+    geometry::{ScreenPoint, ScreenSize, ScreenVector},
+    graphics::LCD_COLUMNS,
+};
+
+const SCREEN_WIDTH: i32 = LCD_COLUMNS as i32;
+
+// The size of the cards in assets/cards; any other size needs its own table.
+const ASSET_CARD_WIDTH: i32 = 50;
+const ASSET_CARD_HEIGHT: i32 = 70;
+const CARDS_PATH: &str = "assets/cards";
+// A nearest-neighbour scale of assets/cards, cell by cell.
+const LARGE_CARDS_PATH: &str = "assets/cards_large";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout {
+    pub margin: i32,
+    pub gutter: i32,
+    pub card_width: i32,
+    pub card_height: i32,
+    pub fan_step: i32,
+    // How far the selected card in a fan is raised; zero leaves selection
+    // to the cursor alone.
+    pub peek_lift: i32,
+    // Stock and waste on the right, foundations on the left.
+    pub mirrored: bool,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            margin: 10,
+            gutter: 5,
+            card_width: ASSET_CARD_WIDTH,
+            card_height: ASSET_CARD_HEIGHT,
+            fan_step: 10,
+            peek_lift: ASSET_CARD_HEIGHT / 4,
+            mirrored: false,
+        }
+    }
+}
+
+impl Layout {
+    #[allow(unused)]
+    pub fn compact() -> Self {
+        Self {
+            margin: 4,
+            gutter: 2,
+            fan_step: 8,
+            ..Self::default()
+        }
+    }
+
+    // Bigger cards for the accessibility setting, with the margins squeezed
+    // so all seven tableaux still fit across the screen.
+    pub fn large() -> Self {
+        Self {
+            card_width: 54,
+            card_height: 76,
+            peek_lift: 76 / 4,
+            ..Self::compact()
+        }
+    }
+
+    pub fn card_size(&self) -> ScreenSize {
+        ScreenSize::new(self.card_width, self.card_height)
+    }
+
+    pub fn cards_table_path(&self) -> &'static str {
+        if self.card_size() == ScreenSize::new(ASSET_CARD_WIDTH, ASSET_CARD_HEIGHT) {
+            CARDS_PATH
+        } else {
+            LARGE_CARDS_PATH
+        }
+    }
+
+    pub fn card_center(&self) -> ScreenVector {
+        ScreenVector::new(self.card_width, self.card_height) / 2
+    }
+
+    pub fn fan_down(&self) -> ScreenVector {
+        ScreenVector::new(0, self.fan_step)
+    }
+
+    pub fn fan_right(&self) -> ScreenVector {
+        if self.mirrored {
+            ScreenVector::new(-self.fan_step, 0)
+        } else {
+            ScreenVector::new(self.fan_step, 0)
+        }
+    }
+
+    fn place(&self, x: i32, y: i32) -> ScreenPoint {
+        if self.mirrored {
+            ScreenPoint::new(SCREEN_WIDTH - x - self.card_width, y)
+        } else {
+            ScreenPoint::new(x, y)
+        }
+    }
+
+    pub fn stock_position(&self) -> ScreenPoint {
+        self.place(self.margin, self.margin)
+    }
+
+    pub fn waste_position(&self) -> ScreenPoint {
+        self.place(self.margin + self.gutter + self.card_width, self.margin)
+    }
+
+    pub fn foundation_position(&self, index: usize) -> ScreenPoint {
+        let count = FOUNDATIONS.len() as i32;
+        let left = SCREEN_WIDTH - count * self.card_width - (count - 1) * self.gutter - self.margin;
+        self.place(
+            left + index as i32 * (self.card_width + self.gutter),
+            self.margin,
+        )
+    }
+
+    pub fn tableau_position(&self, index: usize) -> ScreenPoint {
+        self.place(
+            self.margin + index as i32 * (self.card_width + self.gutter),
+            self.margin + self.card_height + self.gutter,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::klondike::TABLEAUX;
+
+    // Pulled in at build time, so a table that's missing or renamed fails to
+    // compile rather than falling back at run time.
+    const CARD_TABLES: &[(&str, &[u8])] = &[
+        (
+            CARDS_PATH,
+            include_bytes!("../assets/cards-table-50-70.png"),
+        ),
+        (
+            LARGE_CARDS_PATH,
+            include_bytes!("../assets/cards_large-table-54-76.png"),
+        ),
+    ];
+
+    #[test]
+    fn test_cards_table_paths_resolve() {
+        for layout in &[Layout::default(), Layout::large()] {
+            let (_, png) = CARD_TABLES
+                .iter()
+                .find(|(path, _)| *path == layout.cards_table_path())
+                .expect("cards table");
+            let dimension = |at: usize| {
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(&png[at..at + 4]);
+                u32::from_be_bytes(bytes) as i32
+            };
+            // Thirteen ranks across, a row of backs and four suits down.
+            assert_eq!(dimension(16), 13 * layout.card_width);
+            assert_eq!(dimension(20), 5 * layout.card_height);
+        }
+    }
+
+    #[test]
+    fn test_default_layout_matches_original_positions() {
+        let layout = Layout::default();
+        assert_eq!(layout.stock_position(), ScreenPoint::new(10, 10));
+        assert_eq!(layout.waste_position(), ScreenPoint::new(65, 10));
+        assert_eq!(layout.foundation_position(0), ScreenPoint::new(175, 10));
+        assert_eq!(layout.foundation_position(3), ScreenPoint::new(340, 10));
+        assert_eq!(layout.tableau_position(0), ScreenPoint::new(10, 85));
+        assert_eq!(layout.tableau_position(6), ScreenPoint::new(340, 85));
+        assert_eq!(layout.card_center(), ScreenVector::new(25, 35));
+        assert_eq!(layout.peek_lift, layout.card_height / 4);
+        assert_eq!(layout.card_size(), ScreenSize::new(50, 70));
+        assert_eq!(layout.cards_table_path(), CARDS_PATH);
+    }
+
+    #[test]
+    fn test_large_layout_fits_on_screen() {
+        let layout = Layout::large();
+        assert!(layout.card_width > Layout::default().card_width);
+        let waste_right = layout.waste_position().x + 2 * layout.fan_step + layout.card_width;
+        assert!(waste_right < layout.foundation_position(0).x);
+        let last_tableau = layout.tableau_position(TABLEAUX.len() - 1);
+        assert!(last_tableau.x + layout.card_width <= SCREEN_WIDTH);
+        assert_eq!(layout.cards_table_path(), LARGE_CARDS_PATH);
+    }
+
+    #[test]
+    fn test_compact_layout_fits_on_screen() {
+        let layout = Layout::compact();
+        let waste_right = layout.waste_position().x + 2 * layout.fan_step + layout.card_width;
+        assert!(waste_right < layout.foundation_position(0).x);
+        let last_tableau = layout.tableau_position(TABLEAUX.len() - 1);
+        assert!(last_tableau.x + layout.card_width <= SCREEN_WIDTH);
+    }
+
+    #[test]
+    fn test_mirrored_layout_flips_about_center() {
+        let layout = Layout::default();
+        let mirrored = Layout {
+            mirrored: true,
+            ..layout
+        };
+        assert_eq!(mirrored.stock_position(), ScreenPoint::new(340, 10));
+        assert_eq!(mirrored.waste_position(), ScreenPoint::new(285, 10));
+        assert_eq!(mirrored.foundation_position(0), ScreenPoint::new(175, 10));
+        assert_eq!(mirrored.foundation_position(3), ScreenPoint::new(10, 10));
+        assert_eq!(mirrored.tableau_position(0), ScreenPoint::new(340, 85));
+        assert_eq!(mirrored.fan_right(), -layout.fan_right());
+        assert_eq!(mirrored.fan_down(), layout.fan_down());
+    }
+}
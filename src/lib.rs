@@ -3,46 +3,50 @@
 
 extern crate alloc;
 
+mod daily;
+mod game;
 #[allow(dead_code)]
 mod klondike;
+mod layout;
+mod save;
+mod settings;
+mod stats;
+mod tutorial;
 
-use crate::klondike::*;
-use alloc::{boxed::Box, collections::BTreeMap, format, string::String, vec::Vec};
+use crate::{
+    daily::{daily_seed, date_from_day, day_from_seconds},
+    game::{GameState, Input, Recording},
+    klondike::*,
+    layout::Layout,
+    save::{AutosaveInterval, SavedGame},
+    settings::{ReplaySpeed, Setting, Settings},
+    stats::Stats,
+    tutorial::{TUTORIAL_SEED, TUTORIAL_STEPS},
+};
+use alloc::{boxed::Box, collections::BTreeMap, format, rc::Rc, string::String, vec, vec::Vec};
 use anyhow::Error;
-use core::{iter, mem};
+use core::{
+    cell::Cell,
+    iter, mem,
+    sync::atomic::{AtomicBool, Ordering},
+};
+#[cfg(feature = "sim_debug")]
+use crankstart::file::{FileOptions, FileSystem};
 use crankstart::{
-    crankstart_game,
-    geometry::{ScreenPoint, ScreenVector},
+    display::Display,
+    geometry::{ScreenPoint, ScreenRect, ScreenSize, ScreenVector},
     graphics::{
         Bitmap, BitmapTable, Font, Graphics, LCDBitmapDrawMode, LCDBitmapFlip, LCDColor, LCDRect,
         LCDSolidColor, LCD_COLUMNS, LCD_ROWS,
     },
     log_to_console,
-    system::{PDButtons, System},
+    system::{MenuItem, PDButtons, System},
     Game, Playdate,
 };
 use enum_iterator::IntoEnumIterator;
 use euclid::{Point2D};
 use hashbrown::HashMap;
-use rand::{prelude::*, seq::SliceRandom, SeedableRng};
-
-const WINABLE_SEEDS: &[u64] = &[
-    322, 331, 341, 1004, 1006, 1013, 1016, 1018, 1021, 1023, 1026, 1032, 1038, 1040, 1041, 1042,
-    1044, 1055, 1056, 1058, 1061, 1064, 1079, 1082, 1088, 1093, 1095, 1104, 1113, 1118, 1119, 1120,
-    1125, 1132, 1138, 1145, 1146, 1165, 1172, 1176, 1177, 1178, 1180, 1181, 1191, 1193, 1195, 1203,
-    1207, 1208, 1211, 1215, 1219, 1222, 1225, 1227, 1229, 1231, 1239, 1240, 1244, 1245, 1247, 1248,
-    1249, 1252, 1256, 1265, 1272, 1273, 1274, 1275, 1277, 1278, 1291, 1293, 1295, 1306, 1307, 1308,
-    1312, 1318, 1320, 1329, 1330, 1336, 1341, 1354, 1357, 1360, 1362, 1366, 1367, 1369, 1373, 1378,
-    1379, 1380, 1382, 1385, 1386, 1397, 1409, 1415, 1418, 1428, 1434, 1435, 1441, 1447, 1448, 1451,
-    1455, 1458, 1460, 1463, 1466, 1476, 1477, 1478, 1481, 1497, 1499, 1512, 1515, 1518, 1520, 1527,
-    1532, 1536, 1541, 1542, 1545, 1556, 1557, 1561, 1562, 1573, 1581, 1585, 1592, 1599, 1600, 1602,
-    1616, 1621, 1622, 1623, 1624, 1625, 1627, 1628, 1631, 1632, 1639, 1642, 1653, 1657, 1659, 1660,
-    1668, 1678, 1679, 1682, 1683, 1684, 1694, 1712, 1714, 1731, 1748, 1750, 1753, 1754, 1758, 1762,
-    1764, 1777, 1778, 1791, 1808, 1812, 1813, 1816, 1825, 1846, 1851, 1860, 1864, 1866, 1867, 1869,
-    1872, 1876, 1882, 1884, 1886, 1889, 1891, 1893, 1896, 1901, 1902, 1904, 1906, 1916, 1920, 1921,
-    1922, 1927, 1929, 1934, 1935, 1943, 1944, 1946, 1954, 1955, 1956, 1959, 1968, 1972, 1978, 1987,
-    1990, 1993,
-];
+use rand::{prelude::*, SeedableRng};
 
 const SCREEN_CLIP: LCDRect = LCDRect {
     left: 0,
@@ -51,17 +55,66 @@ const SCREEN_CLIP: LCDRect = LCDRect {
     bottom: LCD_ROWS as i32,
 };
 
-const SCREEN_WIDTH: i32 = LCD_COLUMNS as i32;
 //const SCREEN_HEIGHT: i32 = LCD_ROWS as i32;
-const MARGIN: i32 = 10;
 //const INDEX_MARGIN_X: i32 = 4;
 //const INDEX_MARGIN_Y: i32 = 1;
-const GUTTER: i32 = 5;
-const CARD_WIDTH: i32 = 50;
-const CARD_HEIGHT: i32 = 70;
 
 const CRANK_THRESHHOLD: i32 = 10;
 
+const DEAL_FRAMES_PER_CARD: usize = 3;
+const REPLAY_FRAMES_PER_PLAY: usize = 15;
+const AUTO_COMPLETE_FRAMES_PER_PLAY: usize = 6;
+
+// How long the stacks a move touched stay outlined; the outer ring goes
+// halfway through.
+const LAST_MOVE_FRAMES: usize = 12;
+
+const FONT_PATH: &str = "/System/Fonts/Roobert-10-Bold.pft";
+
+// One full bob of the cursor, a few pixels either side of its resting place.
+const POINT_BOB: &[i32] = &[0, 1, 2, 3, 3, 2, 1, 0, -1, -2, -3, -3, -2, -1];
+const POINT_BOB_FRAMES_PER_STEP: usize = 2;
+
+// Each frame the cursor covers this fraction of the remaining distance.
+const CURSOR_TWEEN_DIVISOR: i32 = 3;
+
+const SUIT_BADGE_WIDTH: i32 = 11;
+const SUIT_BADGE_HEIGHT: i32 = 13;
+const SUIT_BADGE_INSET: i32 = 2;
+
+const OPTIONS_WIDTH: i32 = 200;
+const OPTIONS_ROW_HEIGHT: i32 = 18;
+const OPTIONS_PADDING: i32 = 8;
+// Past this many settings the list scrolls to keep the selection in view.
+const OPTIONS_VISIBLE_ROWS: usize = 11;
+
+const HELP_WIDTH: i32 = 320;
+
+const LABEL_HEIGHT: i32 = 16;
+const LABEL_PADDING: i32 = 4;
+
+// The outline drawn just outside each card when borders are on.
+const CARD_BORDER: i32 = 1;
+
+// A bar along the top edge that fills as cards go up to the foundations.
+const PROGRESS_HEIGHT: i32 = 3;
+
+// Holding B this long with cards in hand puts them back instead.
+const LONG_PRESS_MS: usize = 500;
+
+// With no input or animation for this long the display slows down to save
+// power.
+const IDLE_AFTER_MS: usize = 30_000;
+const IDLE_REFRESH_RATE: f32 = 5.0;
+const REFRESH_RATE: f32 = 30.0;
+
+#[cfg(feature = "sim_debug")]
+const SIM_DEBUG_SEED: u64 = 322;
+
+// Where the sim_debug board dump goes, ready for klondike_solver --from-stdin.
+#[cfg(feature = "sim_debug")]
+const BOARD_DUMP_PATH: &str = "board.txt";
+
 #[derive(Debug)]
 enum FanDirection {
     Down,
@@ -79,39 +132,179 @@ struct StackView {
     stack_id: StackId,
     position: ScreenPoint,
     mode: StackDrawMode,
+    layout: Layout,
+    // One entry per visible card; rebuilt by invalidate().
+    positions: Vec<ScreenPoint>,
 }
 
 impl StackView {
-    pub fn get_card_position(&self, index: usize) -> ScreenPoint {
+    fn new(stack_id: StackId, position: ScreenPoint, mode: StackDrawMode, layout: Layout) -> Self {
+        let mut view = Self {
+            stack_id,
+            position,
+            mode,
+            layout,
+            positions: Vec::new(),
+        };
+        view.invalidate();
+        view
+    }
+
+    // Must be called whenever the position, mode or layout changes.
+    pub fn invalidate(&mut self) {
+        let count = match &self.mode {
+            StackDrawMode::Squared => 1,
+            StackDrawMode::Fanned(_, visible) => (*visible).max(1),
+        };
+        self.positions = (0..count)
+            .map(|index| self.compute_card_position(index))
+            .collect();
+    }
+
+    fn compute_card_position(&self, index: usize) -> ScreenPoint {
         let (vector, count) = match &self.mode {
             StackDrawMode::Squared => (ScreenVector::zero(), 0),
             StackDrawMode::Fanned(direction, visible) => match direction {
-                FanDirection::Down => (ScreenVector::new(0, MARGIN), *visible),
-                FanDirection::Right => (ScreenVector::new(MARGIN, 0), *visible),
+                FanDirection::Down => (self.layout.fan_down(), *visible),
+                FanDirection::Right => (self.layout.fan_right(), *visible),
             },
         };
         let number = index.min(count.saturating_sub(1));
         self.position + vector * number as i32
     }
 
+    pub fn get_card_position(&self, index: usize) -> ScreenPoint {
+        self.positions[index.min(self.positions.len() - 1)]
+    }
+
+    // Only the top few cards of a fan are spread out, so the true top is
+    // always the last of them and everything older sits squared under the
+    // first.
+    fn fanned_start(&self, stack: &Stack) -> usize {
+        let visible = match &self.mode {
+            StackDrawMode::Squared => 1,
+            StackDrawMode::Fanned(_, visible) => *visible,
+        };
+        stack.len().saturating_sub(visible)
+    }
+
+    // Where the card at index in the stack is drawn.
+    fn stack_card_position(&self, stack: &Stack, index: usize) -> ScreenPoint {
+        self.get_card_position(index.saturating_sub(self.fanned_start(stack)))
+    }
+
+    // Where the next card put on the stack will be drawn.
+    fn landing_position(&self, stack: &Stack) -> ScreenPoint {
+        self.get_card_position(stack.len())
+    }
+
+    // Everything the view of a stack of len cards can cover, including a
+    // card lifted out of a fan and the borders around the cards.
+    fn bounds(&self, len: usize) -> ScreenRect {
+        let size = self.layout.card_size();
+        let first = ScreenRect::new(self.position, size);
+        let cards = match &self.mode {
+            StackDrawMode::Squared => first,
+            StackDrawMode::Fanned(_, visible) => {
+                let last = self.get_card_position(len.min(*visible).saturating_sub(1));
+                let lifted = first.translate(ScreenVector::new(0, -self.layout.peek_lift));
+                first.union(&ScreenRect::new(last, size)).union(&lifted)
+            }
+        };
+        cards.inflate(CARD_BORDER, CARD_BORDER)
+    }
+
+    // Index of the topmost card drawn under point, or zero over an empty
+    // stack's outline. Lifted cards are found where they rest.
+    fn card_at(&self, stack: &Stack, point: ScreenPoint) -> Option<usize> {
+        let size = self.layout.card_size();
+        let covers = |position| ScreenRect::new(position, size).contains(point);
+        if stack.is_empty() {
+            return if covers(self.position) { Some(0) } else { None };
+        }
+        match &self.mode {
+            StackDrawMode::Squared => {
+                if covers(self.position) {
+                    Some(stack.top_card_index())
+                } else {
+                    None
+                }
+            }
+            StackDrawMode::Fanned(..) => (self.fanned_start(stack)..stack.len())
+                .rev()
+                .find(|index| covers(self.stack_card_position(stack, *index))),
+        }
+    }
+
     #[allow(unused)]
     pub fn get_top_card_position(&self, stack: &Stack) -> ScreenPoint {
         let index = if stack.is_empty() { 0 } else { stack.len() - 1 };
         self.get_card_position(index)
     }
 
-    fn draw_empty(&self, resources: &Resources) -> Result<(), Error> {
-        resources.empty.draw(
-            self.position,
-            LCDBitmapFlip::kBitmapUnflipped,
-        )?;
+    fn draw_empty(&self, resources: &Resources, settings: &Settings) -> Result<(), Error> {
+        resources.draw_bitmap(&resources.empty, self.position)?;
+        match self.stack_id.foundation_suit() {
+            Some(suit) if settings.foundation_hints => self.draw_foundation_hint(suit, resources),
+            _ => Ok(()),
+        }
+    }
+
+    fn draw_foundation_hint(&self, suit: Suit, resources: &Resources) -> Result<(), Error> {
+        let graphics = &resources.graphics;
+        let letter = suit.letter();
+        let text_width = graphics.get_text_width(&resources.font, letter, 0)?;
+        let center = self.position + self.layout.card_center();
+        let offset = ScreenVector::new(text_width, LABEL_HEIGHT) / 2;
+        graphics.draw_text(letter, center - offset)?;
+        Ok(())
+    }
+
+    fn suit_badge_rect(&self, position: &ScreenPoint) -> ScreenRect {
+        // Anchored inside the top right corner so it never extends past the card.
+        ScreenRect::new(
+            *position
+                + ScreenVector::new(
+                    self.layout.card_width - SUIT_BADGE_WIDTH - SUIT_BADGE_INSET,
+                    SUIT_BADGE_INSET,
+                ),
+            ScreenSize::new(SUIT_BADGE_WIDTH, SUIT_BADGE_HEIGHT),
+        )
+    }
+
+    fn draw_suit_badge(
+        &self,
+        card: &Card,
+        position: &ScreenPoint,
+        resources: &Resources,
+    ) -> Result<(), Error> {
+        let graphics = &resources.graphics;
+        let rect = self.suit_badge_rect(position);
+        let letter = card.suit.letter();
+        let text_width = graphics.get_text_width(&resources.font, letter, 0)?;
+        let text_position = rect.origin + ScreenVector::new((rect.size.width - text_width) / 2, 0);
+        match card.suit {
+            Suit::Club | Suit::Spade => {
+                graphics.fill_rect(rect, LCDColor::Solid(LCDSolidColor::kColorBlack))?;
+                graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeFillWhite)?;
+                graphics.draw_text(letter, text_position)?;
+                graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+            }
+            Suit::Diamond | Suit::Heart => {
+                graphics.fill_rect(rect, LCDColor::Solid(LCDSolidColor::kColorWhite))?;
+                graphics.draw_rect(rect, LCDColor::Solid(LCDSolidColor::kColorBlack))?;
+                graphics.draw_text(letter, text_position)?;
+            }
+        }
         Ok(())
     }
 
     fn draw_card_at(
+        &self,
         card: &Card,
         position: &ScreenPoint,
         resources: &Resources,
+        settings: &Settings,
     ) -> Result<(), Error> {
         let bitmap = if card.face_up {
             if let Some(bitmap) = resources.card_bitmaps.get(&(card.suit, card.rank)) {
@@ -122,27 +315,28 @@ impl StackView {
         } else {
             &resources.back
         };
-        bitmap.draw(
-            *position,
-            LCDBitmapFlip::kBitmapUnflipped,
-        )?;
+        if settings.card_borders {
+            let rect = ScreenRect::new(*position, self.layout.card_size());
+            resources.graphics.draw_rect(
+                rect.inflate(CARD_BORDER, CARD_BORDER),
+                LCDColor::Solid(LCDSolidColor::kColorBlack),
+            )?;
+        }
+        resources.draw_bitmap(bitmap, *position)?;
+        if card.face_up && settings.high_contrast_suits {
+            self.draw_suit_badge(card, position, resources)?;
+        }
         Ok(())
     }
 
-    fn draw_squared(&self, stack: &Stack, resources: &Resources) -> Result<(), Error> {
+    fn draw_squared(
+        &self,
+        stack: &Stack,
+        resources: &Resources,
+        settings: &Settings,
+    ) -> Result<(), Error> {
         if let Some(card) = stack.get_top_card() {
-            let bitmap = if card.face_up {
-                resources
-                    .card_bitmaps
-                    .get(&(card.suit, card.rank))
-                    .unwrap_or(&resources.empty)
-            } else {
-                &resources.back
-            };
-            bitmap.draw(
-                self.position,
-                LCDBitmapFlip::kBitmapUnflipped,
-            )?;
+            self.draw_card_at(card, &self.position, resources, settings)?;
         }
         Ok(())
     }
@@ -151,48 +345,62 @@ impl StackView {
         &self,
         stack: &Stack,
         resources: &Resources,
+        settings: &Settings,
         source: &Source,
-        direction: &FanDirection,
-        visible: usize,
     ) -> Result<(), Error> {
-        let cards_in_stack = stack.len();
-        let cards_to_draw = cards_in_stack.min(visible);
-        let mut card_pos = self.position;
-
-        let fan_vector = match direction {
-            FanDirection::Down => ScreenVector::new(0, MARGIN),
-            FanDirection::Right => ScreenVector::new(MARGIN, 0),
-        };
-
-        let start = cards_in_stack - cards_to_draw;
-        let max_index = cards_in_stack - 1;
-        for index in start..cards_in_stack {
+        let max_index = stack.len().saturating_sub(1);
+        for index in self.fanned_start(stack)..stack.len() {
+            let card_pos = self.stack_card_position(stack, index);
             if let Some(card) = stack.get_card(index) {
-                if card.face_up
+                if self.layout.peek_lift != 0
+                    && card.face_up
                     && index < max_index
                     && index == source.index
                     && stack.stack_id == source.stack
                 {
-                    let peeked = card_pos - ScreenVector::new(0, CARD_HEIGHT / 4);
-                    Self::draw_card_at(card, &peeked, resources)?;
+                    let peeked = card_pos - ScreenVector::new(0, self.layout.peek_lift);
+                    self.draw_card_at(card, &peeked, resources, settings)?;
                 } else {
-                    Self::draw_card_at(card, &card_pos, resources)?;
+                    self.draw_card_at(card, &card_pos, resources, settings)?;
                 }
             }
-            card_pos += fan_vector;
         }
 
         Ok(())
     }
 
-    fn draw(&self, source: &Source, stack: &Stack, resources: &Resources) -> Result<(), Error> {
+    fn draw_dealing(
+        &self,
+        stack: &Stack,
+        resources: &Resources,
+        settings: &Settings,
+        placed: usize,
+    ) -> Result<(), Error> {
+        if placed == 0 {
+            self.draw_empty(resources, settings)?;
+        } else {
+            for (index, card) in stack.iter().take(placed).enumerate() {
+                let position = self.get_card_position(index);
+                self.draw_card_at(card, &position, resources, settings)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn draw(
+        &self,
+        source: &Source,
+        stack: &Stack,
+        resources: &Resources,
+        settings: &Settings,
+    ) -> Result<(), Error> {
         if stack.is_empty() {
-            self.draw_empty(resources)?;
+            self.draw_empty(resources, settings)?;
         } else {
             match &self.mode {
-                StackDrawMode::Squared => self.draw_squared(stack, resources)?,
-                StackDrawMode::Fanned(direction, visible) => {
-                    self.draw_fanned(stack, resources, source, direction, *visible)?
+                StackDrawMode::Squared => self.draw_squared(stack, resources, settings)?,
+                StackDrawMode::Fanned(..) => {
+                    self.draw_fanned(stack, resources, settings, source)?
                 }
             }
         }
@@ -204,22 +412,215 @@ struct Resources {
     card_bitmaps: HashMap<(Suit, Rank), Bitmap>,
     back: Bitmap,
     empty: Bitmap,
-    #[allow(unused)]
     graphics: Graphics,
     point: Bitmap,
+    point_size: ScreenSize,
+    font: Font,
+    #[cfg(feature = "debug_hud")]
+    draw_calls: Cell<usize>,
+}
+
+impl Resources {
+    fn draw_bitmap(&self, bitmap: &Bitmap, position: ScreenPoint) -> Result<(), Error> {
+        #[cfg(feature = "debug_hud")]
+        self.draw_calls.set(self.draw_calls.get() + 1);
+        bitmap.draw(position, LCDBitmapFlip::kBitmapUnflipped)
+    }
+}
+
+// Everything drawn outside the stacks; any change to it redraws the whole screen.
+#[derive(Clone, Copy, PartialEq)]
+struct OverlayState {
+    seconds: usize,
+    moves: u32,
+    daily_solved: bool,
+    options_index: Option<usize>,
+    confirming_new_game: bool,
+    help: bool,
+    tutorial: Option<usize>,
+    cards_remaining: usize,
+    face_down: usize,
+    replay_speed: Option<ReplaySpeed>,
+    seed: Option<u64>,
+    last_move: Option<(Play, usize)>,
+}
+
+// Just enough of a stack as it was drawn to tell when it needs drawing again;
+// every move changes its length or its top card.
+#[derive(PartialEq)]
+struct DrawnStack {
+    len: usize,
+    top: Option<Card>,
+}
+
+impl DrawnStack {
+    fn new(stack: &Stack) -> Self {
+        Self {
+            len: stack.len(),
+            top: stack.top_card().cloned(),
+        }
+    }
+}
+
+struct DrawnFrame {
+    stacks: HashMap<StackId, DrawnStack>,
+    source: Source,
+    hand_position: ScreenPoint,
+    cursor: ScreenRect,
+    overlay: OverlayState,
+}
+
+#[cfg(feature = "debug_hud")]
+#[derive(Default)]
+struct DebugHud {
+    window_start_ms: usize,
+    window_frames: usize,
+    fps: usize,
+}
+
+#[cfg(feature = "debug_hud")]
+impl DebugHud {
+    fn advance(&mut self, now: usize) {
+        self.window_frames += 1;
+        let elapsed = now.saturating_sub(self.window_start_ms);
+        if elapsed >= 1000 {
+            self.fps = self.window_frames * 1000 / elapsed;
+            self.window_start_ms = now;
+            self.window_frames = 0;
+        }
+    }
+}
+
+struct Dealing {
+    order: Vec<Source>,
+    frame: usize,
+}
+
+impl Dealing {
+    fn new() -> Self {
+        let order = (0..TABLEAUX.len())
+            .flat_map(|row| {
+                TABLEAUX[row..]
+                    .iter()
+                    .map(move |tableau| Source::new(*tableau, row))
+            })
+            .collect();
+        Self { order, frame: 0 }
+    }
+
+    fn placed(&self) -> usize {
+        self.frame / DEAL_FRAMES_PER_CARD
+    }
+
+    fn placed_in(&self, stack_id: StackId) -> usize {
+        self.order[..self.placed().min(self.order.len())]
+            .iter()
+            .filter(|source| source.stack == stack_id)
+            .count()
+    }
+
+    fn in_flight(&self) -> Option<(Source, usize)> {
+        self.order
+            .get(self.placed())
+            .map(|source| (*source, self.frame % DEAL_FRAMES_PER_CARD))
+    }
+
+    fn is_done(&self) -> bool {
+        self.placed() >= self.order.len()
+    }
+
+    fn advance(&mut self) {
+        self.frame += 1;
+    }
+}
+
+// The controls as the settings have them now.
+fn help_lines(settings: &Settings) -> Vec<&'static str> {
+    let mut lines = vec![
+        "A: pick up or put down cards",
+        "B: undo, or drop cards; hold to put back",
+    ];
+    lines.push(if settings.stop_at_ends {
+        "Left/Right or crank: move, stops at the ends"
+    } else {
+        "Left/Right or crank: move"
+    });
+    if settings.tableaux_first {
+        lines.push("The tableaux come before the stock");
+    }
+    if settings.left_handed {
+        lines.push("The stock is on the right");
+    }
+    if settings.auto_recycle {
+        lines.push("Moving to an empty stock turns the waste");
+    }
+    lines.push("Up/Down during replays: speed");
+    lines.push("Menu: options, new game, difficulty");
+    lines.push("Up: close this help");
+    lines
+}
+
+// The first setting shown when the one at index is selected.
+fn options_first_row(index: usize, count: usize) -> usize {
+    (index + 1)
+        .saturating_sub(OPTIONS_VISIBLE_ROWS)
+        .min(count.saturating_sub(OPTIONS_VISIBLE_ROWS))
 }
 
 struct KlondikeGame {
-    table: Table,
-    active_cards: Vec<Source>,
-    source_index: usize,
-    targets: Vec<StackId>,
-    target_index: usize,
+    game: GameState,
+    dealing: Option<Dealing>,
+    replay: Option<vec::IntoIter<Play>>,
+    auto_completing: bool,
+    replay_speed: ReplaySpeed,
+    last_frame_ms: usize,
     views: HashMap<StackId, StackView>,
+    layout: Layout,
     #[allow(unused)]
     cards_table: BitmapTable,
     resources: Resources,
     crank_threshhold: i32,
+    settings: Settings,
+    #[allow(unused)]
+    options_menu_item: MenuItem,
+    options_requested: Rc<Cell<bool>>,
+    options_index: Option<usize>,
+    #[allow(unused)]
+    new_game_menu_item: MenuItem,
+    new_game_requested: Rc<Cell<bool>>,
+    difficulty_menu_item: MenuItem,
+    difficulty_requested: Rc<Cell<bool>>,
+    difficulty: Difficulty,
+    // Picked from the menu, and only taken up once the new game is.
+    pending_difficulty: Option<Difficulty>,
+    // A setting that deals a new game, switched back if that's turned down.
+    pending_setting: Option<Setting>,
+    confirming_new_game: bool,
+    help: bool,
+    rng: rand_pcg::Pcg32,
+    day: usize,
+    stats: Stats,
+    win_recorded: bool,
+    autosave: AutosaveInterval,
+    save_generation: u32,
+    // The game's revision and the clock as of the last save.
+    saved_revision: u32,
+    last_save_ms: usize,
+    // Index of the next tutorial step while the tutorial is running.
+    tutorial: Option<usize>,
+    frame: usize,
+    // A move the player didn't make card by card, the card it moved and the
+    // frame it happened.
+    last_move: Option<(Play, Option<Card>, usize)>,
+    cursor_position: Option<ScreenPoint>,
+    // When B went down over held cards; it acts on release or long press.
+    b_held_since: Option<usize>,
+    last_input_ms: usize,
+    idle: bool,
+    // What's on screen now, so the next frame only redraws what changed.
+    drawn: Option<DrawnFrame>,
+    #[cfg(feature = "debug_hud")]
+    hud: DebugHud,
 }
 
 impl KlondikeGame {
@@ -246,209 +647,1088 @@ impl KlondikeGame {
         let back = cards_table.get_bitmap(4)?;
         let empty = cards_table.get_bitmap(0)?;
         let point = graphics.load_bitmap("assets/point")?;
+        let point_data = point.get_data()?;
+        let point_size = ScreenSize::new(point_data.width, point_data.height);
+        let font = graphics.load_font(FONT_PATH)?;
+        graphics.set_font(&font)?;
         Ok(Resources {
             card_bitmaps,
             back,
             empty,
             graphics,
             point,
+            point_size,
+            font,
+            #[cfg(feature = "debug_hud")]
+            draw_calls: Cell::new(0),
         })
     }
 
-    fn update_active_cards(&mut self) {
-        self.active_cards = iter::once(Source::stock())
-            .chain(ActiveCardIterator::new(&self.table))
-            .collect();
+    // The stack and card drawn under a screen point, ignoring the hand.
+    #[allow(unused)]
+    fn stack_at(&self, point: ScreenPoint) -> Option<(StackId, usize)> {
+        Self::find_stack_at(&self.views, &self.game.table, point)
     }
 
-    fn update_targets(&mut self) {
-        let source = self.table.source;
-
-        self.targets = StackId::into_enum_iter()
-            .filter(|stack_id| {
-                *stack_id == source.stack || self.table.stack_can_accept_hand(*stack_id)
+    fn find_stack_at(
+        views: &HashMap<StackId, StackView>,
+        table: &Table,
+        point: ScreenPoint,
+    ) -> Option<(StackId, usize)> {
+        views
+            .values()
+            .filter(|view| view.stack_id != StackId::Hand)
+            .find_map(|view| {
+                let index = view.card_at(table.get_stack(view.stack_id), point)?;
+                Some((view.stack_id, index))
             })
-            .collect();
-        self.target_index = self
-            .targets
-            .iter()
-            .position(|stack_id| *stack_id == source.stack)
-            .unwrap_or(0);
-    }
-
-    fn go_previous(&mut self) {
-        if self.table.cards_in_hand() {
-            if self.target_index == 0 {
-                self.target_index = self.targets.len().saturating_sub(1);
-            } else {
-                self.target_index -= 1;
-            }
-            self.table.target = self.targets[self.target_index];
-        } else {
-            if self.source_index == 0 {
-                self.source_index = self.active_cards.len().saturating_sub(1);
-            } else {
-                self.source_index -= 1;
-            }
-            self.table.source = self.active_cards[self.source_index];
-        }
     }
 
-    fn go_next(&mut self) {
-        if self.table.cards_in_hand() {
-            let max_index = self.targets.len().saturating_sub(1);
-            if self.target_index == max_index {
-                self.target_index = 0;
-            } else {
-                self.target_index += 1;
-            }
-            self.table.target = self.targets[self.target_index];
-        } else {
-            if self.active_cards.len() > 0 {
-                let max_index = self.active_cards.len().saturating_sub(1);
-                if self.source_index >= max_index {
-                    self.source_index = 0;
-                } else {
-                    self.source_index += 1;
-                }
-                self.table.source = self.active_cards[self.source_index];
-            }
-        }
+    // Layouts whose cards aren't the standard size have a table of their own.
+    fn load_cards_table(layout: &Layout, graphics: &Graphics) -> Result<BitmapTable, Error> {
+        graphics.load_bitmap_table(layout.cards_table_path())
     }
 
-    pub fn new(_playdate: &Playdate) -> Result<Box<Self>, Error> {
-        let (secs, _) = System::get().get_seconds_since_epoch()?;
-        let mut rng = rand_pcg::Pcg32::seed_from_u64(secs as u64);
-        let seed = WINABLE_SEEDS.choose(&mut rng).expect("seed");
-        let table = Table::new(*seed);
-        let graphics = Graphics::get();
-        let cards_table = graphics.load_bitmap_table("assets/cards")?;
-
-        let foundation_gutter_count = (FOUNDATIONS.len() - 1) as i32;
-        let mut position = ScreenPoint::new(
-            SCREEN_WIDTH
-                - FOUNDATIONS.len() as i32 * 50
-                - foundation_gutter_count * GUTTER
-                - MARGIN,
-            MARGIN,
-        );
-
-        let foundations = FOUNDATIONS.iter().map(|foundation| {
-            let stack = StackView {
-                stack_id: *foundation,
-                position,
-                mode: StackDrawMode::Squared,
-            };
-            position.x += CARD_WIDTH + GUTTER;
-            stack
+    fn build_views(layout: Layout) -> HashMap<StackId, StackView> {
+        let foundations = FOUNDATIONS.iter().enumerate().map(|(index, foundation)| {
+            StackView::new(
+                *foundation,
+                layout.foundation_position(index),
+                StackDrawMode::Squared,
+                layout,
+            )
         });
 
-        let mut position = ScreenPoint::new(MARGIN, MARGIN + CARD_HEIGHT + GUTTER);
-        let mut stack_count = 1;
-        let tableaux = TABLEAUX.iter().map(|tableau| {
-            let stack = StackView {
-                stack_id: *tableau,
-                position,
-                mode: StackDrawMode::Fanned(FanDirection::Down, 52),
-            };
-            stack_count += 1;
-            position.x += 55;
-            stack
+        let tableaux = TABLEAUX.iter().enumerate().map(|(index, tableau)| {
+            StackView::new(
+                *tableau,
+                layout.tableau_position(index),
+                StackDrawMode::Fanned(FanDirection::Down, 52),
+                layout,
+            )
         });
 
-        let stock = StackView {
-            stack_id: StackId::Stock,
-            position: ScreenPoint::new(MARGIN, MARGIN),
-            mode: StackDrawMode::Squared,
-        };
-        let waste = StackView {
-            stack_id: StackId::Waste,
-            position: ScreenPoint::new(MARGIN + GUTTER + CARD_WIDTH, MARGIN),
-            mode: StackDrawMode::Fanned(FanDirection::Right, 3),
-        };
-        let in_hand = StackView {
-            stack_id: StackId::Hand,
-            position: ScreenPoint::zero(),
-            mode: StackDrawMode::Squared,
-        };
+        let stock = StackView::new(
+            StackId::Stock,
+            layout.stock_position(),
+            StackDrawMode::Squared,
+            layout,
+        );
+        let waste = StackView::new(
+            StackId::Waste,
+            layout.waste_position(),
+            StackDrawMode::Fanned(FanDirection::Right, 3),
+            layout,
+        );
+        let in_hand = StackView::new(
+            StackId::Hand,
+            ScreenPoint::zero(),
+            StackDrawMode::Fanned(FanDirection::Down, 13),
+            layout,
+        );
 
-        let views: HashMap<StackId, StackView> = foundations
+        foundations
             .chain(tableaux)
             .chain(iter::once(stock))
             .chain(iter::once(waste).chain(iter::once(in_hand)))
             .map(|stack_view| (stack_view.stack_id, stack_view))
-            .collect();
+            .collect()
+    }
+
+    pub fn new(_playdate: &Playdate) -> Result<Box<Self>, Error> {
+        let (secs, _) = System::get().get_seconds_since_epoch()?;
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(secs as u64);
+        let seed = random_seed(&mut rng);
+        let day = day_from_seconds(secs);
+        let graphics = Graphics::get();
+        let layout = Layout::default();
+        let cards_table = Self::load_cards_table(&layout, &graphics)?;
+        let views = Self::build_views(layout);
         let resources = Self::load_resources(&cards_table, Graphics::get())?;
-        let active_cards = iter::once(Source::stock())
-            .chain(ActiveCardIterator::new(&table))
+        // The system menu only has room for three items, so settings live
+        // in an overlay of our own.
+        let options_requested = Rc::new(Cell::new(false));
+        let requested = options_requested.clone();
+        let options_menu_item =
+            System::get().add_menu_item("Options", Box::new(move || requested.set(true)))?;
+        let new_game_requested = Rc::new(Cell::new(false));
+        let requested = new_game_requested.clone();
+        let new_game_menu_item =
+            System::get().add_menu_item("New game", Box::new(move || requested.set(true)))?;
+        let difficulty_requested = Rc::new(Cell::new(false));
+        let requested = difficulty_requested.clone();
+        let difficulties = Difficulty::into_enum_iter()
+            .map(|difficulty| difficulty.label().into())
             .collect();
-        Ok(Box::new(Self {
-            table,
-            active_cards,
-            source_index: 0,
-            targets: Vec::new(),
-            target_index: 0,
+        let difficulty_menu_item = System::get().add_options_menu_item(
+            "Difficulty",
+            difficulties,
+            Box::new(move || requested.set(true)),
+        )?;
+        let difficulty = Difficulty::default();
+        let index = Difficulty::into_enum_iter()
+            .position(|preset| preset == difficulty)
+            .unwrap_or(0);
+        System::get().set_menu_item_value(&difficulty_menu_item, index)?;
+        let game = GameState::new(seed);
+        let last_frame_ms = System::get().get_current_time_milliseconds()?;
+        let mut klondike = Box::new(Self {
+            game,
+            dealing: Some(Dealing::new()),
+            replay: None,
+            auto_completing: false,
+            replay_speed: ReplaySpeed::default(),
+            last_frame_ms,
             views,
+            layout,
             cards_table,
             resources,
             crank_threshhold: 0,
-        }))
+            settings: Settings::default(),
+            options_menu_item,
+            options_requested,
+            options_index: None,
+            new_game_menu_item,
+            new_game_requested,
+            difficulty_menu_item,
+            difficulty_requested,
+            difficulty,
+            pending_difficulty: None,
+            pending_setting: None,
+            confirming_new_game: false,
+            help: false,
+            rng,
+            day,
+            stats: Stats::load(),
+            win_recorded: false,
+            autosave: AutosaveInterval::default(),
+            save_generation: 0,
+            saved_revision: 0,
+            last_save_ms: last_frame_ms,
+            tutorial: None,
+            frame: 0,
+            last_move: None,
+            cursor_position: None,
+            b_held_since: None,
+            last_input_ms: last_frame_ms,
+            idle: false,
+            drawn: None,
+            #[cfg(feature = "debug_hud")]
+            hud: DebugHud::default(),
+        });
+        if let Some(saved) = SavedGame::load() {
+            klondike.resume(saved)?;
+        }
+        Ok(klondike)
+    }
+
+    // Plays a saved game back up to where it was left, without the replay.
+    fn resume(&mut self, saved: SavedGame) -> Result<(), Error> {
+        self.difficulty = saved.difficulty;
+        self.show_difficulty()?;
+        self.deal(saved.recording.seed);
+        // The deal animation would show the cards as they were first dealt.
+        self.dealing = None;
+        for play in saved.recording.plays {
+            if self.game.apply_play(play).is_err() {
+                break;
+            }
+        }
+        self.game.set_elapsed_ms(saved.elapsed_ms);
+        self.save_generation = saved.generation;
+        self.saved_revision = self.game.revision();
+        Ok(())
+    }
+
+    fn changes_since_save(&self) -> u32 {
+        self.game.revision().wrapping_sub(self.saved_revision)
+    }
+
+    fn update_autosave(&mut self) -> Result<(), Error> {
+        // Replays and the tutorial are replayable on their own.
+        if self.replay.is_some() || self.tutorial.is_some() || self.game.table.winner() {
+            return Ok(());
+        }
+        let changes = self.changes_since_save();
+        let ms_since_save = self.last_frame_ms.saturating_sub(self.last_save_ms);
+        if !self.autosave.is_due(changes, ms_since_save) {
+            return Ok(());
+        }
+        self.save_generation = self.save_generation.wrapping_add(1);
+        let saved = SavedGame {
+            generation: self.save_generation,
+            difficulty: self.difficulty,
+            recording: self.game.recording().clone(),
+            elapsed_ms: self.game.elapsed_ms(),
+        };
+        saved.save()?;
+        self.saved_revision = self.game.revision();
+        self.last_save_ms = self.last_frame_ms;
+        Ok(())
+    }
+
+    fn is_paused(&self) -> bool {
+        self.game.is_paused()
     }
 
     fn check_crank(&mut self, _playdate: &mut Playdate) -> Result<(), Error> {
         let change = System::get().get_crank_change()? as i32;
+        if change != 0 {
+            self.last_input_ms = self.last_frame_ms;
+        }
+        if self.is_paused() {
+            return Ok(());
+        }
         self.crank_threshhold += change;
 
         if self.crank_threshhold > CRANK_THRESHHOLD {
-            self.go_next();
+            self.game.handle_input(Input::Next);
             self.crank_threshhold = -CRANK_THRESHHOLD;
         } else if self.crank_threshhold < -CRANK_THRESHHOLD {
-            self.go_previous();
+            self.game.handle_input(Input::Previous);
             self.crank_threshhold = CRANK_THRESHHOLD;
         }
         Ok(())
     }
 
     fn check_buttons(&mut self, _playdate: &mut Playdate) -> Result<(), Error> {
-        let (_, pushed, _) = System::get().get_button_state()?;
-        if (pushed & PDButtons::kButtonA) == PDButtons::kButtonA
-            || (pushed & PDButtons::kButtonB) == PDButtons::kButtonB
+        let (current, pushed, _) = System::get().get_button_state()?;
+        let buttons = [
+            PDButtons::kButtonLeft,
+            PDButtons::kButtonRight,
+            PDButtons::kButtonUp,
+            PDButtons::kButtonDown,
+            PDButtons::kButtonB,
+            PDButtons::kButtonA,
+        ];
+        if buttons.iter().any(|button| (current & *button) == *button) {
+            self.last_input_ms = self.last_frame_ms;
+        }
+        #[cfg(feature = "sim_debug")]
+        {
+            if self.check_debug_buttons(current, pushed) {
+                return Ok(());
+            }
+        }
+        if self.confirming_new_game {
+            return self.check_confirm_buttons(pushed);
+        }
+        if let Some(index) = self.options_index {
+            return self.check_options_buttons(index, pushed);
+        }
+        if self.help {
+            let closing = [
+                PDButtons::kButtonUp,
+                PDButtons::kButtonA,
+                PDButtons::kButtonB,
+            ];
+            if closing.iter().any(|button| (pushed & *button) == *button) {
+                self.help = false;
+            }
+            return Ok(());
+        }
+        if self.replay.is_none()
+            && self.game.table.winner()
+            && (pushed & PDButtons::kButtonA) == PDButtons::kButtonA
+        {
+            self.start_replay(self.game.recording().clone());
+            return Ok(());
+        }
+        // The system menu is full, so Up and Down set the speed while
+        // moves play themselves.
+        if self.replay_speed_shown().is_some() {
+            if (pushed & PDButtons::kButtonUp) == PDButtons::kButtonUp {
+                self.replay_speed = self.replay_speed.faster();
+            } else if (pushed & PDButtons::kButtonDown) == PDButtons::kButtonDown {
+                self.replay_speed = self.replay_speed.slower();
+            }
+        }
+        if self.tutorial == Some(TUTORIAL_STEPS.len())
+            && (pushed & PDButtons::kButtonA) == PDButtons::kButtonA
         {
-            if self.table.cards_in_hand() {
-                self.table.put_hand_on_target();
-                self.update_active_cards();
+            self.settings.tutorial = false;
+            return self.new_game();
+        }
+        if self.is_paused() {
+            return Ok(());
+        }
+        if let Some(since) = self.b_held_since {
+            if (current & PDButtons::kButtonB) != PDButtons::kButtonB {
+                self.b_held_since = None;
+                self.game.handle_input(Input::Select);
+            } else if self.last_frame_ms.saturating_sub(since) >= LONG_PRESS_MS {
+                self.b_held_since = None;
+                self.game.handle_input(Input::Cancel);
+            }
+            return Ok(());
+        }
+        if (pushed & PDButtons::kButtonA) == PDButtons::kButtonA {
+            self.game.handle_input(Input::Select);
+        } else if (pushed & PDButtons::kButtonB) == PDButtons::kButtonB {
+            // B still drops a held card, or puts it back if held down;
+            // otherwise it takes back a move.
+            if self.game.table.cards_in_hand() {
+                self.b_held_since = Some(self.last_frame_ms);
             } else {
-                match self.table.source.stack {
-                    StackId::Stock => {
-                        self.table.deal_from_stock();
-                        self.update_active_cards();
-                    }
-                    StackId::Waste
-                    | StackId::Foundation1
-                    | StackId::Foundation2
-                    | StackId::Foundation3
-                    | StackId::Foundation4 => {
-                        self.table.take_top_card_from_stack(self.table.source.stack)
-                    }
-                    StackId::Tableau1
-                    | StackId::Tableau2
-                    | StackId::Tableau3
-                    | StackId::Tableau4
-                    | StackId::Tableau5
-                    | StackId::Tableau6
-                    | StackId::Tableau7 => self.table.take_selected_cards_from_stack(
-                        self.table.source.stack,
-                        self.table.source.index,
-                    ),
-                    StackId::Hand => (),
+                let undone = self.game.recording().plays.last().copied();
+                self.game.handle_input(Input::Undo);
+                // Undone cards are back where the play picked them up.
+                if let Some(play) = undone {
+                    self.show_move(play, self.game.table.moved_card(play));
                 }
-                self.table.target = self.table.source.stack;
-                self.update_targets();
             }
+        } else if (pushed & PDButtons::kButtonUp) == PDButtons::kButtonUp {
+            self.help = true;
         } else if pushed & PDButtons::kButtonLeft == PDButtons::kButtonLeft {
-            self.go_previous();
+            self.game.handle_input(Input::Previous);
         } else if pushed & PDButtons::kButtonRight == PDButtons::kButtonRight {
-            self.go_next();
+            self.game.handle_input(Input::Next);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "sim_debug")]
+    fn check_debug_buttons(&mut self, current: PDButtons, pushed: PDButtons) -> bool {
+        if (current & PDButtons::kButtonB) != PDButtons::kButtonB {
+            return false;
+        }
+        if (pushed & PDButtons::kButtonUp) == PDButtons::kButtonUp {
+            // Straight on the table, so undo can't take it back.
+            if !self.game.table.auto_complete() {
+                log_to_console!("auto_complete stuck");
+            }
+        } else if (pushed & PDButtons::kButtonDown) == PDButtons::kButtonDown {
+            log_to_console!("{:#?}", self.game.table);
+        } else if (pushed & PDButtons::kButtonLeft) == PDButtons::kButtonLeft {
+            match self.dump_board() {
+                Ok(()) => log_to_console!("board written to {}", BOARD_DUMP_PATH),
+                Err(error) => log_to_console!("board dump failed: {}", error),
+            }
+        } else if (pushed & PDButtons::kButtonA) == PDButtons::kButtonA {
+            self.deal(SIM_DEBUG_SEED);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    #[cfg(feature = "sim_debug")]
+    fn dump_board(&self) -> Result<(), Error> {
+        let file = FileSystem::get().open(BOARD_DUMP_PATH, FileOptions::kFileWrite)?;
+        file.write(self.game.board_dump().as_bytes())?;
+        file.flush()?;
+        Ok(())
+    }
+
+    fn check_options_buttons(&mut self, index: usize, pushed: PDButtons) -> Result<(), Error> {
+        let count = Setting::into_enum_iter().count();
+        if (pushed & PDButtons::kButtonUp) == PDButtons::kButtonUp {
+            self.options_index = Some((index + count - 1) % count);
+        } else if (pushed & PDButtons::kButtonDown) == PDButtons::kButtonDown {
+            self.options_index = Some((index + 1) % count);
+        } else if (pushed & PDButtons::kButtonA) == PDButtons::kButtonA {
+            let setting = Setting::into_enum_iter().nth(index).expect("setting");
+            self.settings.toggle(setting);
+            self.apply_setting(setting)?;
+        } else if (pushed & PDButtons::kButtonB) == PDButtons::kButtonB {
+            self.options_index = None;
+        }
+        Ok(())
+    }
+
+    fn apply_setting(&mut self, setting: Setting) -> Result<(), Error> {
+        match setting {
+            Setting::LeftHanded => {
+                // Only the views move; the table and navigation order are untouched.
+                self.layout.mirrored = self.settings.left_handed;
+                self.views = Self::build_views(self.layout);
+            }
+            Setting::LargeCards => {
+                let layout = if self.settings.large_cards {
+                    Layout::large()
+                } else {
+                    Layout::default()
+                };
+                self.layout = Layout {
+                    mirrored: self.settings.left_handed,
+                    ..layout
+                };
+                self.views = Self::build_views(self.layout);
+                self.cards_table = Self::load_cards_table(&self.layout, &self.resources.graphics)?;
+                self.resources = Self::load_resources(&self.cards_table, Graphics::get())?;
+            }
+            Setting::DailyDeal | Setting::Tutorial => {
+                self.pending_setting = Some(setting);
+                self.request_new_game()?;
+            }
+            Setting::FoundationsFirst => self
+                .game
+                .set_foundations_first(self.settings.foundations_first),
+            Setting::AutoRecycle => self.game.set_auto_recycle(self.settings.auto_recycle),
+            Setting::TableauxFirst => self.game.set_navigation(self.navigation()),
+            Setting::StopAtEnds => self.game.set_wrap(!self.settings.stop_at_ends),
+            Setting::SaveEveryMove => {
+                self.autosave = if self.settings.save_every_move {
+                    AutosaveInterval::every_move()
+                } else {
+                    AutosaveInterval::default()
+                };
+            }
+            Setting::HighContrastSuits
+            | Setting::ReduceMotion
+            | Setting::FoundationHints
+            | Setting::RevealCards
+            | Setting::CardBorders
+            | Setting::HighVisibilityCursor => (),
+        }
+        Ok(())
+    }
+
+    fn navigation(&self) -> NavigationOrder {
+        if self.settings.tableaux_first {
+            NavigationOrder::TableauxFirst
+        } else {
+            NavigationOrder::Standard
+        }
+    }
+
+    fn new_game(&mut self) -> Result<(), Error> {
+        let table = &self.game.table;
+        if self.tutorial.is_none() && table.move_count() > 0 && !table.winner() {
+            self.stats
+                .record_abandoned(self.game.furthest_progress(), self.game.fewest_face_down());
+            self.stats.save()?;
+        }
+        let (secs, _) = System::get().get_seconds_since_epoch()?;
+        self.day = day_from_seconds(secs);
+        let seed = if self.settings.tutorial {
+            TUTORIAL_SEED
+        } else if self.settings.daily_deal {
+            daily_seed(self.day)
+        } else {
+            random_seed(&mut self.rng)
+        };
+        self.deal(seed);
+        SavedGame::clear()
+    }
+
+    fn deal(&mut self, seed: u64) {
+        self.game = GameState::new(seed);
+        // The tutorial's steps are written for the standard rules.
+        if !self.settings.tutorial {
+            self.game.set_difficulty(self.difficulty);
+        }
+        self.game
+            .set_foundations_first(self.settings.foundations_first);
+        self.game.set_auto_recycle(self.settings.auto_recycle);
+        self.game.set_navigation(self.navigation());
+        self.game.set_wrap(!self.settings.stop_at_ends);
+        self.dealing = Some(Dealing::new());
+        self.replay = None;
+        self.auto_completing = false;
+        self.win_recorded = false;
+        self.saved_revision = 0;
+        self.last_save_ms = self.last_frame_ms;
+        self.tutorial = if self.settings.tutorial {
+            Some(0)
+        } else {
+            None
+        };
+        self.cursor_position = None;
+        self.b_held_since = None;
+        self.last_move = None;
+    }
+
+    fn update_requests(&mut self) -> Result<(), Error> {
+        if self.options_requested.replace(false) {
+            self.options_index = Some(0);
+        }
+        if self.new_game_requested.replace(false) {
+            self.request_new_game()?;
+        }
+        if self.difficulty_requested.replace(false) {
+            let index = System::get().get_menu_item_value(&self.difficulty_menu_item)?;
+            if let Some(difficulty) = Difficulty::into_enum_iter().nth(index) {
+                if difficulty != self.difficulty {
+                    self.pending_difficulty = Some(difficulty);
+                    self.request_new_game()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Only a game with progress to lose needs confirming.
+    fn request_new_game(&mut self) -> Result<(), Error> {
+        let table = &self.game.table;
+        if table.move_count() > 0 && !table.winner() {
+            self.options_index = None;
+            self.confirming_new_game = true;
+            Ok(())
+        } else {
+            self.start_new_game()
+        }
+    }
+
+    fn start_new_game(&mut self) -> Result<(), Error> {
+        if let Some(difficulty) = self.pending_difficulty.take() {
+            self.difficulty = difficulty;
+        }
+        self.pending_setting = None;
+        self.new_game()
+    }
+
+    fn show_difficulty(&self) -> Result<(), Error> {
+        let index = Difficulty::into_enum_iter()
+            .position(|preset| preset == self.difficulty)
+            .unwrap_or(0);
+        System::get().set_menu_item_value(&self.difficulty_menu_item, index)
+    }
+
+    fn check_confirm_buttons(&mut self, pushed: PDButtons) -> Result<(), Error> {
+        if (pushed & PDButtons::kButtonA) == PDButtons::kButtonA {
+            self.confirming_new_game = false;
+            self.start_new_game()?;
+        } else if (pushed & PDButtons::kButtonB) == PDButtons::kButtonB {
+            // The game carries on as it was, so the menu goes back too.
+            self.confirming_new_game = false;
+            if self.pending_difficulty.take().is_some() {
+                self.show_difficulty()?;
+            }
+            if let Some(setting) = self.pending_setting.take() {
+                self.settings.toggle(setting);
+            }
+        }
+        Ok(())
+    }
+
+    fn update_tutorial(&mut self) {
+        let step = match self.tutorial {
+            Some(step) if step < TUTORIAL_STEPS.len() => step,
+            _ => return,
+        };
+        let played = self.game.recording().plays.get(step).copied();
+        match played {
+            Some(play) if play == TUTORIAL_STEPS[step].play => self.tutorial = Some(step + 1),
+            // Anything off script is taken back so the later steps still line up.
+            Some(_) => self.game.handle_input(Input::Undo),
+            None => (),
+        }
+    }
+
+    fn update_idle(&mut self) -> Result<(), Error> {
+        let animating = self.dealing.is_some()
+            || self.replay.is_some()
+            || self.auto_completing
+            || self.last_move_highlight().is_some()
+            || self.b_held_since.is_some();
+        let idle =
+            !animating && self.last_frame_ms.saturating_sub(self.last_input_ms) >= IDLE_AFTER_MS;
+        if idle == self.idle {
+            return Ok(());
+        }
+        self.idle = idle;
+        if idle {
+            Display::get().set_refresh_rate(IDLE_REFRESH_RATE)
+        } else {
+            // Start the first frame back from a clean screen.
+            self.drawn = None;
+            Display::get().set_refresh_rate(REFRESH_RATE)
+        }
+    }
+
+    fn update_paused(&mut self) {
+        let paused = self.dealing.is_some()
+            || self.replay.is_some()
+            || self.auto_completing
+            || self.options_index.is_some()
+            || self.confirming_new_game
+            || self.help;
+        self.game.set_paused(paused);
+    }
+
+    fn update_stats(&mut self) -> Result<(), Error> {
+        if self.win_recorded || !self.game.table.winner() {
+            return Ok(());
+        }
+        self.win_recorded = true;
+        SavedGame::clear()?;
+        if let Some(result) = self.game.result() {
+            log_to_console!("{}", result.to_share_string());
+        }
+        self.stats.record_win(self.game.elapsed_ms());
+        if self.settings.daily_deal {
+            self.stats.complete_daily(self.day);
+        }
+        self.stats.save()
+    }
+
+    fn point_offset(&self) -> ScreenVector {
+        if self.settings.reduce_motion {
+            ScreenVector::zero()
+        } else {
+            let step = (self.frame / POINT_BOB_FRAMES_PER_STEP) % POINT_BOB.len();
+            ScreenVector::new(0, POINT_BOB[step])
+        }
+    }
+
+    fn tween_cursor(&mut self, target: ScreenPoint) -> ScreenPoint {
+        let current = self.cursor_position.unwrap_or(target);
+        let step = (target - current) / CURSOR_TWEEN_DIVISOR;
+        // Integer division stalls a few pixels short, so finish with a snap.
+        let position = if self.settings.reduce_motion || step == ScreenVector::zero() {
+            target
+        } else {
+            current + step
+        };
+        self.cursor_position = Some(position);
+        position
+    }
+
+    fn start_replay(&mut self, recording: Recording) {
+        self.game = GameState::new(recording.seed);
+        if !self.settings.tutorial {
+            self.game.set_difficulty(self.difficulty);
+        }
+        self.replay = Some(recording.plays.into_iter());
+    }
+
+    fn replay_speed_shown(&self) -> Option<ReplaySpeed> {
+        if self.replay.is_some() || self.auto_completing {
+            Some(self.replay_speed)
+        } else {
+            None
+        }
+    }
+
+    fn update_replay(&mut self) {
+        if self.frame % self.replay_speed.frames(REPLAY_FRAMES_PER_PLAY) != 0 {
+            return;
+        }
+        let play = self.replay.as_mut().and_then(|plays| plays.next());
+        let card = play.and_then(|play| self.game.table.moved_card(play));
+        // A recording that no longer applies ends the replay early.
+        match play {
+            Some(play) if self.game.apply_play(play).is_ok() => self.show_move(play, card),
+            _ => self.replay = None,
+        }
+    }
+
+    // Steps the highlight through the cards auto-play put up, one at a time.
+    fn update_auto_plays(&mut self) {
+        if self.last_move_highlight().is_none() {
+            if let Some((play, card)) = self.game.next_auto_play() {
+                self.show_move(play, card);
+            }
+        }
+    }
+
+    fn show_move(&mut self, play: Play, card: Option<Card>) {
+        self.last_move = Some((play, card, self.frame));
+    }
+
+    // The move to outline and how many rings to draw around it.
+    fn last_move_highlight(&self) -> Option<(Play, usize)> {
+        let (play, _, frame) = self.last_move.as_ref()?;
+        match self.frame.wrapping_sub(*frame) {
+            age if age < LAST_MOVE_FRAMES / 2 => Some((*play, 2)),
+            age if age < LAST_MOVE_FRAMES => Some((*play, 1)),
+            _ => None,
+        }
+    }
+
+    fn update_auto_complete(&mut self) {
+        if self.auto_completing {
+            let frames = self.replay_speed.frames(AUTO_COMPLETE_FRAMES_PER_PLAY);
+            if self.options_index.is_none() && self.frame % frames == 0 {
+                let play = self.game.table.next_auto_complete_play();
+                let card = play.and_then(|play| self.game.table.moved_card(play));
+                match play {
+                    Some(play) if self.game.apply_play(play).is_ok() => self.show_move(play, card),
+                    _ => self.auto_completing = false,
+                }
+            }
+        } else if !self.is_paused() && !self.game.table.winner() && self.game.can_auto_complete() {
+            self.auto_completing = true;
+        }
+    }
+
+    fn hand_position(&self) -> ScreenPoint {
+        let table = &self.game.table;
+        let target = table.get_stack(table.target);
+        let position = self
+            .views
+            .get(&table.target)
+            .map(|view| view.landing_position(target))
+            .unwrap_or_else(ScreenPoint::zero);
+        // Lift a long run so the bottom of the fan stays on screen.
+        let fan_height = self.layout.fan_step * table.hand_card_count().saturating_sub(1) as i32;
+        let lowest = LCD_ROWS as i32 - self.layout.card_height - fan_height;
+        ScreenPoint::new(position.x, position.y.min(lowest))
+    }
+
+    fn overlay_state(&self) -> OverlayState {
+        OverlayState {
+            seconds: self.game.elapsed_ms() / 1000,
+            moves: self.game.table.move_count(),
+            daily_solved: self.settings.daily_deal && self.stats.is_daily_completed(self.day),
+            options_index: self.options_index,
+            confirming_new_game: self.confirming_new_game,
+            help: self.help,
+            tutorial: self.tutorial,
+            cards_remaining: self.game.table.cards_remaining_to_win(),
+            face_down: self.game.table.count_face_down(),
+            replay_speed: self.replay_speed_shown(),
+            seed: self.game.table.seed(),
+            last_move: self.last_move_highlight(),
+        }
+    }
+
+    // The areas that changed since the last frame, or None when the whole
+    // screen has to be drawn again.
+    fn dirty_rects(&self, frame: &DrawnFrame) -> Option<Vec<ScreenRect>> {
+        let drawn = self.drawn.as_ref()?;
+        // Highlights reach outside the stacks they point at.
+        if drawn.overlay != frame.overlay
+            || frame.overlay.tutorial.is_some()
+            || frame.overlay.last_move.is_some()
+        {
+            return None;
+        }
+        let mut rects = vec![drawn.cursor, frame.cursor];
+        let source_moved = drawn.source != frame.source;
+        for (stack_id, view) in &self.views {
+            let old = &drawn.stacks[stack_id];
+            let new = &frame.stacks[stack_id];
+            let offset = if *stack_id == StackId::Hand {
+                drawn.hand_position - frame.hand_position
+            } else {
+                ScreenVector::zero()
+            };
+            let lifted = source_moved
+                && (drawn.source.stack == *stack_id || frame.source.stack == *stack_id);
+            if old != new || lifted || offset != ScreenVector::zero() {
+                rects.push(view.bounds(old.len).translate(offset));
+                rects.push(view.bounds(new.len));
+            }
+        }
+        Some(rects)
+    }
+
+    fn update_clock(&mut self) -> Result<(), Error> {
+        let now = System::get().get_current_time_milliseconds()?;
+        let delta = now.saturating_sub(self.last_frame_ms);
+        self.last_frame_ms = now;
+        #[cfg(feature = "debug_hud")]
+        self.hud.advance(now);
+        // update() isn't called while the system menu is up, so the first
+        // frame back spans all the time spent in it.
+        if !SYSTEM_MENU_SHOWN.swap(false, Ordering::Relaxed) {
+            self.game.advance(delta);
+        }
+        Ok(())
+    }
+}
+
+impl KlondikeGame {
+    fn label_width(&self, text: &str) -> Result<i32, Error> {
+        let width = self
+            .resources
+            .graphics
+            .get_text_width(&self.resources.font, text, 0)?;
+        Ok(width + 2 * LABEL_PADDING)
+    }
+
+    fn draw_label(&self, text: &str, position: ScreenPoint) -> Result<(), Error> {
+        let graphics = &self.resources.graphics;
+        let rect = ScreenRect::new(
+            position,
+            ScreenSize::new(self.label_width(text)?, LABEL_HEIGHT),
+        );
+        graphics.fill_rect(rect, LCDColor::Solid(LCDSolidColor::kColorWhite))?;
+        graphics.draw_text(text, position + ScreenVector::new(LABEL_PADDING, 0))?;
+        Ok(())
+    }
+
+    fn draw_prompt(&self, text: &str) -> Result<(), Error> {
+        let size = ScreenSize::new(self.label_width(text)?, LABEL_HEIGHT);
+        let origin = ScreenPoint::new(
+            (LCD_COLUMNS as i32 - size.width) / 2,
+            (LCD_ROWS as i32 - size.height) / 2,
+        );
+        self.draw_label(text, origin)?;
+        self.resources.graphics.draw_rect(
+            ScreenRect::new(origin, size),
+            LCDColor::Solid(LCDSolidColor::kColorBlack),
+        )?;
+        Ok(())
+    }
+
+    fn draw_help(&self) -> Result<(), Error> {
+        let graphics = &self.resources.graphics;
+        let lines = help_lines(&self.settings);
+        let size = ScreenSize::new(
+            HELP_WIDTH,
+            lines.len() as i32 * OPTIONS_ROW_HEIGHT + 2 * OPTIONS_PADDING,
+        );
+        let origin = ScreenPoint::new(
+            (LCD_COLUMNS as i32 - size.width) / 2,
+            (LCD_ROWS as i32 - size.height) / 2,
+        );
+        let rect = ScreenRect::new(origin, size);
+        graphics.fill_rect(rect, LCDColor::Solid(LCDSolidColor::kColorWhite))?;
+        graphics.draw_rect(rect, LCDColor::Solid(LCDSolidColor::kColorBlack))?;
+        for (row, line) in lines.iter().enumerate() {
+            let row_origin = origin
+                + ScreenVector::new(
+                    OPTIONS_PADDING,
+                    OPTIONS_PADDING + row as i32 * OPTIONS_ROW_HEIGHT,
+                );
+            graphics.draw_text(line, row_origin)?;
+        }
+        Ok(())
+    }
+
+    fn draw_options(&self, index: usize) -> Result<(), Error> {
+        let graphics = &self.resources.graphics;
+        let count = Setting::into_enum_iter().count();
+        let first = options_first_row(index, count);
+        let rows = count.min(OPTIONS_VISIBLE_ROWS) as i32;
+        let size = ScreenSize::new(
+            OPTIONS_WIDTH,
+            rows * OPTIONS_ROW_HEIGHT + 2 * OPTIONS_PADDING,
+        );
+        let origin = ScreenPoint::new(
+            (LCD_COLUMNS as i32 - size.width) / 2,
+            (LCD_ROWS as i32 - size.height) / 2,
+        );
+        let rect = ScreenRect::new(origin, size);
+        graphics.fill_rect(rect, LCDColor::Solid(LCDSolidColor::kColorWhite))?;
+        graphics.draw_rect(rect, LCDColor::Solid(LCDSolidColor::kColorBlack))?;
+        let visible = Setting::into_enum_iter()
+            .enumerate()
+            .skip(first)
+            .take(OPTIONS_VISIBLE_ROWS);
+        for (row, setting) in visible {
+            let row_origin = origin
+                + ScreenVector::new(
+                    OPTIONS_PADDING,
+                    OPTIONS_PADDING + (row - first) as i32 * OPTIONS_ROW_HEIGHT,
+                );
+            let check = if self.settings.get(setting) {
+                "[x]"
+            } else {
+                "[ ]"
+            };
+            let text = format!("{} {}", check, setting.label());
+            if row == index {
+                let row_rect = ScreenRect::new(
+                    row_origin,
+                    ScreenSize::new(OPTIONS_WIDTH - 2 * OPTIONS_PADDING, OPTIONS_ROW_HEIGHT),
+                );
+                graphics.fill_rect(row_rect, LCDColor::Solid(LCDSolidColor::kColorBlack))?;
+                graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeFillWhite)?;
+                graphics.draw_text(&text, row_origin)?;
+                graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+            } else {
+                graphics.draw_text(&text, row_origin)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn tutorial_highlight(&self, play: Play) -> Option<ScreenPoint> {
+        let table = &self.game.table;
+        match play {
+            Play::MoveCards(_, target) if table.cards_in_hand() => {
+                let view = self.views.get(&target)?;
+                Some(view.get_top_card_position(table.get_stack(target)))
+            }
+            Play::MoveCards(source, _) => {
+                let view = self.views.get(&source.stack)?;
+                Some(view.stack_card_position(table.get_stack(source.stack), source.index))
+            }
+            _ => self.views.get(&StackId::Stock).map(|view| view.position),
+        }
+    }
+
+    fn draw_tutorial(&self, step: usize) -> Result<(), Error> {
+        let position = ScreenPoint::new(self.layout.margin, LCD_ROWS as i32 - 2 * LABEL_HEIGHT);
+        let tutorial_step = match TUTORIAL_STEPS.get(step) {
+            Some(tutorial_step) => tutorial_step,
+            None => return self.draw_label("Tutorial done! A=new game", position),
+        };
+        self.draw_label(tutorial_step.text, position)?;
+        if let Some(origin) = self.tutorial_highlight(tutorial_step.play) {
+            self.draw_highlight(origin, 2)?;
+        }
+        Ok(())
+    }
+
+    fn draw_highlight(&self, origin: ScreenPoint, rings: usize) -> Result<(), Error> {
+        let rect = ScreenRect::new(origin, self.layout.card_size());
+        let color = LCDColor::Solid(LCDSolidColor::kColorBlack);
+        for ring in 0..rings as i32 {
+            self.resources
+                .graphics
+                .draw_rect(rect.inflate(2 + ring, 2 + ring), color)?;
+        }
+        Ok(())
+    }
+
+    // The top cards of the stacks a play took from and put on.
+    fn play_highlights(&self, play: Play) -> Vec<ScreenPoint> {
+        let stacks = match play {
+            Play::MoveCards(source, target) => [source.stack, target],
+            Play::DrawFromStock => [StackId::Stock, StackId::Waste],
+            Play::RecycleWaste => [StackId::Waste, StackId::Stock],
+            Play::Setup => return Vec::new(),
+        };
+        let table = &self.game.table;
+        stacks
+            .iter()
+            .filter_map(|stack_id| {
+                let stack = table.get_stack(*stack_id);
+                let view = self.views.get(stack_id)?;
+                Some(view.stack_card_position(stack, stack.len().saturating_sub(1)))
+            })
+            .collect()
+    }
+
+    fn draw_last_move(&self) -> Result<(), Error> {
+        if let Some((play, rings)) = self.last_move_highlight() {
+            for origin in self.play_highlights(play) {
+                self.draw_highlight(origin, rings)?;
+            }
+            if let Some((_, Some(card), _)) = &self.last_move {
+                let text = format!("Moved {}", card.label());
+                let position = ScreenPoint::new(
+                    LCD_COLUMNS as i32 - self.layout.margin - self.label_width(&text)?,
+                    LCD_ROWS as i32 - 2 * LABEL_HEIGHT,
+                );
+                self.draw_label(&text, position)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_progress(&self) -> Result<(), Error> {
+        let table = &self.game.table;
+        let total = table.card_count().max(1);
+        let done = total - table.cards_remaining_to_win();
+        let width = LCD_COLUMNS as i32 * done as i32 / total as i32;
+        let rect = ScreenRect::new(ScreenPoint::zero(), ScreenSize::new(width, PROGRESS_HEIGHT));
+        self.resources
+            .graphics
+            .fill_rect(rect, LCDColor::Solid(LCDSolidColor::kColorBlack))
+    }
+
+    fn draw_overlays(&self) -> Result<(), Error> {
+        self.draw_progress()?;
+        if self.settings.daily_deal {
+            let (year, month, day) = date_from_day(self.day);
+            let solved = if self.stats.is_daily_completed(self.day) {
+                " (solved)"
+            } else {
+                ""
+            };
+            let text = format!("Daily {}-{:02}-{:02}{}", year, month, day, solved);
+            let position = ScreenPoint::new(self.layout.margin, LCD_ROWS as i32 - LABEL_HEIGHT);
+            self.draw_label(&text, position)?;
+        } else if let Some(seed) = self.game.table.seed() {
+            let text = format!("Seed: {}", seed);
+            let position = ScreenPoint::new(self.layout.margin, LCD_ROWS as i32 - LABEL_HEIGHT);
+            self.draw_label(&text, position)?;
+        }
+        let seconds = self.game.elapsed_ms() / 1000;
+        let mut status = format!(
+            "{:02}:{:02}  Moves: {}  Hidden: {}",
+            seconds / 60,
+            seconds % 60,
+            self.game.table.move_count(),
+            self.game.table.count_face_down()
+        );
+        if self.game.table.options.scoring {
+            status += &format!("  Score: {}", self.game.table.vegas_score());
+        }
+        let position = ScreenPoint::new(
+            LCD_COLUMNS as i32 - self.layout.margin - self.label_width(&status)?,
+            LCD_ROWS as i32 - LABEL_HEIGHT,
+        );
+        self.draw_label(&status, position)?;
+        self.draw_last_move()?;
+        if let Some(step) = self.tutorial {
+            self.draw_tutorial(step)?;
+        } else if let Some(speed) = self.replay_speed_shown() {
+            let text = format!("Speed {}  Up/Down", speed.label());
+            let position = ScreenPoint::new(self.layout.margin, LCD_ROWS as i32 - 2 * LABEL_HEIGHT);
+            self.draw_label(&text, position)?;
+        }
+        if let Some(index) = self.options_index {
+            self.draw_options(index)?;
+        }
+        if self.help {
+            self.draw_help()?;
+        }
+        if self.confirming_new_game {
+            let text = format!(
+                "{} of {} cards up. New game? A=yes B=no",
+                self.game.furthest_progress(),
+                self.game.table.card_count()
+            );
+            self.draw_prompt(&text)?;
+        } else if let Some(result) = self.game.result() {
+            self.draw_prompt(&result.to_share_string())?;
+        } else if self.game.is_stuck() {
+            let text = format!(
+                "No moves left, {} of {} cards up. B=undo",
+                self.game.furthest_progress(),
+                self.game.table.card_count()
+            );
+            self.draw_prompt(&text)?;
+        }
+        #[cfg(feature = "debug_hud")]
+        self.draw_hud()?;
+        Ok(())
+    }
+
+    #[cfg(feature = "debug_hud")]
+    fn draw_hud(&self) -> Result<(), Error> {
+        // Overlays come last, so this covers every bitmap drawn this frame.
+        let draw_calls = self.resources.draw_calls.replace(0);
+        let text = format!("{} fps  {} draws", self.hud.fps, draw_calls);
+        self.draw_label(&text, ScreenPoint::zero())
+    }
+
+    fn draw_dealing(&mut self, dealing: &Dealing) -> Result<(), Error> {
+        let table = &self.game.table;
+        for (stack_id, view) in &self.views {
+            let stack = table.get_stack(*stack_id);
+            match stack.stack_type {
+                StackType::Tableau => view.draw_dealing(
+                    stack,
+                    &self.resources,
+                    &self.settings,
+                    dealing.placed_in(*stack_id),
+                )?,
+                StackType::Hand => (),
+                _ => view.draw(&table.source, stack, &self.resources, &self.settings)?,
+            }
+        }
+
+        if let Some((source, step)) = dealing.in_flight() {
+            let start = self
+                .views
+                .get(&StackId::Stock)
+                .expect("stock_view")
+                .position;
+            let end = self
+                .views
+                .get(&source.stack)
+                .expect("tableau_view")
+                .get_card_position(source.index);
+            let position = start + (end - start) * step as i32 / DEAL_FRAMES_PER_CARD as i32;
+            self.resources.draw_bitmap(&self.resources.back, position)?;
         }
         Ok(())
     }
@@ -459,53 +1739,340 @@ impl Game for KlondikeGame {
         &mut self,
         playdate: &mut crankstart::Playdate,
     ) -> core::result::Result<(), anyhow::Error> {
+        self.frame = self.frame.wrapping_add(1);
+        self.update_requests()?;
+        self.update_paused();
+        self.update_clock()?;
         self.check_crank(playdate)?;
         self.check_buttons(playdate)?;
+        self.update_auto_plays();
+        self.update_idle()?;
+        self.update_tutorial();
+        self.update_stats()?;
+        self.update_autosave()?;
 
-        let cards_in_hand = self.table.cards_in_hand();
-        if cards_in_hand {
-            let top_card_index = self.table.get_stack(self.table.target).top_card_index();
-            let position = self
-                .views
-                .get(&self.table.target)
-                .and_then(|view| {
-                    Some(view.get_card_position(top_card_index) + ScreenVector::new(10, 10))
-                })
-                .unwrap_or_else(|| ScreenPoint::zero());
-            if let Some(in_hand) = self.views.get_mut(&StackId::Hand) {
-                in_hand.position = position;
+        if let Some(mut dealing) = self.dealing.take() {
+            Graphics::get().clear(LCDColor::Solid(LCDSolidColor::kColorWhite))?;
+            self.draw_dealing(&dealing)?;
+            dealing.advance();
+            if !dealing.is_done() {
+                self.dealing = Some(dealing);
             }
+            self.drawn = None;
+            return self.draw_overlays();
         }
 
-        Graphics::get().clear(LCDColor::Solid(LCDSolidColor::kColorWhite))?;
+        if self.replay.is_some() {
+            self.update_replay();
+        } else {
+            self.update_auto_complete();
+        }
 
-        for (stack_id, view) in &self.views {
-            if *stack_id != StackId::Hand || cards_in_hand {
-                let stack = self.table.get_stack(*stack_id);
-                view.draw(&self.table.source, stack, &self.resources)?;
+        let cards_in_hand = self.game.table.cards_in_hand();
+        let mut hand_position = self.hand_position();
+        if let Some(in_hand) = self.views.get_mut(&StackId::Hand) {
+            if cards_in_hand && in_hand.position != hand_position {
+                in_hand.position = hand_position;
+                in_hand.invalidate();
             }
+            hand_position = in_hand.position;
         }
 
         let position = if cards_in_hand {
-            let target = self.table.get_stack(self.table.target);
-            let target_view = self.views.get(&target.stack_id).expect("target_view");
-            let position =
-                target_view.get_card_position(target.top_card_index()) + ScreenVector::new(10, 10);
-            position
+            hand_position
         } else {
-            let source = self.table.get_stack(self.table.source.stack);
-            let source_view = self.views.get(&source.stack_id).expect("source_view");
-            source_view.get_card_position(self.table.source.index)
+            let table = &self.game.table;
+            let source_view = self.views.get(&table.source.stack).expect("source_view");
+            source_view.stack_card_position(table.get_stack(table.source.stack), table.source.index)
+        };
+        let position = self.tween_cursor(position);
+        let point_position = position + self.layout.card_center() + self.point_offset();
+        let point_rect = ScreenRect::new(point_position, self.resources.point_size);
+        let highlight = if self.settings.high_visibility_cursor {
+            Some(ScreenRect::new(position, self.layout.card_size()).inflate(2, 2))
+        } else {
+            None
         };
 
-        self.resources.point.draw(
-            position + ScreenVector::new(CARD_WIDTH, CARD_HEIGHT) / 2,
-            LCDBitmapFlip::kBitmapUnflipped,
-        )?;
+        // Only the copy being drawn is revealed, so a peek can't make a
+        // buried card playable.
+        let revealed;
+        let table = if self.settings.reveal_cards {
+            let mut copy = self.game.table.clone();
+            copy.reveal_all();
+            revealed = copy;
+            &revealed
+        } else {
+            &self.game.table
+        };
+        let frame = DrawnFrame {
+            stacks: self
+                .views
+                .keys()
+                .map(|stack_id| (*stack_id, DrawnStack::new(table.get_stack(*stack_id))))
+                .collect(),
+            source: table.source,
+            hand_position,
+            // The highlight is cleared and redrawn with the point, so the XOR
+            // always lands on freshly drawn cards.
+            cursor: highlight.map_or(point_rect, |rect| rect.union(&point_rect)),
+            overlay: self.overlay_state(),
+        };
+        let dirty = self.dirty_rects(&frame);
 
-        Ok(())
+        let graphics = Graphics::get();
+        let white = LCDColor::Solid(LCDSolidColor::kColorWhite);
+        match &dirty {
+            Some(rects) => {
+                for rect in rects {
+                    graphics.fill_rect(*rect, white)?;
+                }
+            }
+            None => graphics.clear(white)?,
+        }
+
+        for (stack_id, view) in &self.views {
+            if *stack_id != StackId::Hand || cards_in_hand {
+                let stack = table.get_stack(*stack_id);
+                // Drawing a view again over itself is harmless, so anything
+                // touching a cleared area is drawn whole.
+                let bounds = view.bounds(stack.len());
+                let touched = dirty
+                    .as_ref()
+                    .map(|rects| rects.iter().any(|rect| rect.intersects(&bounds)))
+                    .unwrap_or(true);
+                if touched {
+                    view.draw(&table.source, stack, &self.resources, &self.settings)?;
+                }
+            }
+        }
+
+        if let Some(rect) = highlight {
+            graphics.fill_rect(rect, LCDColor::Solid(LCDSolidColor::kColorXOR))?;
+            graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeXOR)?;
+        }
+        self.resources
+            .draw_bitmap(&self.resources.point, point_position)?;
+        if highlight.is_some() {
+            graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+        }
+        self.drawn = Some(frame);
+
+        self.draw_overlays()
     }
 }
 
+// Set by the system's pause event, which crankstart_game! drops, and cleared
+// by the first update after it.
+static SYSTEM_MENU_SHOWN: AtomicBool = AtomicBool::new(false);
+
+// crankstart_game! with the pause event passed on as well.
 #[cfg(not(test))]
-crankstart_game!(KlondikeGame);
+pub mod game_setup {
+    use super::*;
+    use crankstart::{graphics::PDRect, GameRunner};
+    use crankstart_sys::{LCDSprite, PDSystemEvent, PlaydateAPI};
+
+    static mut GAME_RUNNER: Option<GameRunner<KlondikeGame>> = None;
+
+    extern "C" fn sprite_update(sprite: *mut LCDSprite) {
+        let game_runner = unsafe { GAME_RUNNER.as_mut().expect("GAME_RUNNER") };
+        game_runner.update_sprite(sprite);
+    }
+
+    extern "C" fn sprite_draw(sprite: *mut LCDSprite, bounds: PDRect, drawrect: PDRect) {
+        let game_runner = unsafe { GAME_RUNNER.as_mut().expect("GAME_RUNNER") };
+        game_runner.draw_sprite(sprite, bounds, drawrect);
+    }
+
+    extern "C" fn update(_user_data: *mut core::ffi::c_void) -> i32 {
+        let game_runner = unsafe { GAME_RUNNER.as_mut().expect("GAME_RUNNER") };
+        game_runner.update();
+        1
+    }
+
+    #[no_mangle]
+    extern "C" fn eventHandler(
+        playdate: *mut PlaydateAPI,
+        event: PDSystemEvent,
+        _arg: u32,
+    ) -> crankstart_sys::ctypes::c_int {
+        match event {
+            PDSystemEvent::kEventInit => {
+                let mut playdate = match Playdate::new(playdate, sprite_update, sprite_draw) {
+                    Ok(playdate) => playdate,
+                    Err(e) => {
+                        log_to_console!("Failed to construct Playdate system: {}", e);
+                        return 1;
+                    }
+                };
+                System::get()
+                    .set_update_callback(Some(update))
+                    .unwrap_or_else(|err| {
+                        log_to_console!("Got error while setting update callback: {}", err);
+                    });
+                let game = match KlondikeGame::new(&mut playdate) {
+                    Ok(game) => Some(game),
+                    Err(err) => {
+                        log_to_console!("Got error while creating game: {}", err);
+                        None
+                    }
+                };
+                unsafe {
+                    GAME_RUNNER = Some(GameRunner::new(game, playdate));
+                }
+            }
+            PDSystemEvent::kEventPause => SYSTEM_MENU_SHOWN.store(true, Ordering::Relaxed),
+            _ => (),
+        }
+        0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cached_positions_match_computed() {
+        let layout = Layout {
+            mirrored: true,
+            ..Layout::default()
+        };
+        let mut views = KlondikeGame::build_views(layout);
+        let in_hand = views.get_mut(&StackId::Hand).expect("in_hand");
+        in_hand.position = ScreenPoint::new(100, 40);
+        in_hand.invalidate();
+        for view in views.values() {
+            for index in 0..60 {
+                assert_eq!(
+                    view.get_card_position(index),
+                    view.compute_card_position(index),
+                    "{:?} {}",
+                    view.stack_id,
+                    index
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_hand_lands_where_cards_will_go() {
+        let layout = Layout::default();
+        let views = KlondikeGame::build_views(layout);
+        let card = |rank| Card {
+            rank,
+            suit: Suit::Spade,
+            face_up: true,
+        };
+        let cards = vec![card(Rank::Ace), card(Rank::Two), card(Rank::Three)];
+        let table = Table::from_layout(Vec::new(), Vec::new(), vec![cards.clone()], vec![cards]);
+
+        let foundation = &views[&StackId::Foundation1];
+        let landing = foundation.landing_position(&table.foundations[0]);
+        assert_eq!(landing, layout.foundation_position(0));
+
+        let tableau = &views[&StackId::Tableau1];
+        let landing = tableau.landing_position(&table.tableaux[0]);
+        assert_eq!(landing, layout.tableau_position(0) + layout.fan_down() * 3);
+    }
+
+    #[test]
+    fn test_waste_fans_the_latest_cards_with_the_top_rightmost() {
+        let layout = Layout::default();
+        let views = KlondikeGame::build_views(layout);
+        let waste = &views[&StackId::Waste];
+        let mut table = Table::new(322);
+        let rightmost = layout.waste_position() + layout.fan_right() * 2;
+
+        table.deal_specific(2);
+        let top = table.waste.top_card_index();
+        assert_eq!(top, 1);
+        assert_eq!(
+            waste.stack_card_position(&table.waste, top),
+            layout.waste_position() + layout.fan_right()
+        );
+
+        table.deal_from_stock();
+        table.deal_from_stock();
+        let top = table.waste.top_card_index();
+        assert_eq!(waste.stack_card_position(&table.waste, top), rightmost);
+        assert_eq!(waste.landing_position(&table.waste), rightmost);
+        for index in 0..=top - 2 {
+            assert_eq!(
+                waste.stack_card_position(&table.waste, index),
+                layout.waste_position()
+            );
+        }
+
+        while table.has_cards_in_stock() {
+            table.deal_from_stock();
+        }
+        table.recycle_waste();
+        table.deal_from_stock();
+        let top = table.waste.top_card_index();
+        assert_eq!(waste.stack_card_position(&table.waste, top), rightmost);
+    }
+
+    #[test]
+    fn test_options_fit_on_screen() {
+        let rows = OPTIONS_VISIBLE_ROWS as i32;
+        assert!(rows * OPTIONS_ROW_HEIGHT + 2 * OPTIONS_PADDING <= LCD_ROWS as i32);
+    }
+
+    #[test]
+    fn test_help_follows_the_settings() {
+        let plain = help_lines(&Settings::default());
+        let settings = Settings {
+            stop_at_ends: true,
+            tableaux_first: true,
+            left_handed: true,
+            auto_recycle: true,
+            ..Settings::default()
+        };
+        let everything = help_lines(&settings);
+        assert!(everything.len() > plain.len());
+        let stops = |lines: &[&str]| lines.iter().any(|line| line.contains("stops at the ends"));
+        assert!(stops(&everything));
+        assert!(!stops(&plain));
+        let rows = everything.len() as i32;
+        assert!(rows * OPTIONS_ROW_HEIGHT + 2 * OPTIONS_PADDING <= LCD_ROWS as i32);
+    }
+
+    #[test]
+    fn test_options_scroll_to_the_selection() {
+        let count = OPTIONS_VISIBLE_ROWS + 3;
+        assert_eq!(options_first_row(0, OPTIONS_VISIBLE_ROWS), 0);
+        assert_eq!(
+            options_first_row(OPTIONS_VISIBLE_ROWS - 1, OPTIONS_VISIBLE_ROWS),
+            0
+        );
+        for index in 0..count {
+            let first = options_first_row(index, count);
+            assert!(first <= index && index < first + OPTIONS_VISIBLE_ROWS);
+            assert!(first + OPTIONS_VISIBLE_ROWS <= count);
+        }
+    }
+
+    #[test]
+    fn test_stack_at_finds_topmost_card() {
+        let views = KlondikeGame::build_views(Layout::default());
+        let mut table = Table::new(322);
+        let stack_at = |table: &Table, x, y| {
+            KlondikeGame::find_stack_at(&views, table, ScreenPoint::new(x, y))
+        };
+        let stock_top = table.stock.top_card_index();
+        assert_eq!(stack_at(&table, 20, 20), Some((StackId::Stock, stock_top)));
+        assert_eq!(stack_at(&table, 70, 20), Some((StackId::Waste, 0)));
+        assert_eq!(stack_at(&table, 62, 20), None);
+        assert_eq!(stack_at(&table, 180, 20), Some((StackId::Foundation1, 0)));
+        assert_eq!(stack_at(&table, 125, 100), Some((StackId::Tableau3, 1)));
+        assert_eq!(stack_at(&table, 125, 170), Some((StackId::Tableau3, 2)));
+        assert_eq!(stack_at(&table, 125, 180), None);
+
+        table.deal_from_stock();
+        table.deal_from_stock();
+        assert_eq!(stack_at(&table, 70, 20), Some((StackId::Waste, 3)));
+        assert_eq!(stack_at(&table, 90, 20), Some((StackId::Waste, 5)));
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{game::Recording, klondike::Difficulty};
+use alloc::string::String;
+use anyhow::Error;
+use crankstart::file::{FileOptions, FileSystem};
+use serde::{Deserialize, Serialize};
+
+const SAVE_PATH: &str = "game.json";
+const SAVE_TEMP_PATH: &str = "game.json.tmp";
+
+// How often a game in progress is written out, whichever comes first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AutosaveInterval {
+    pub moves: u32,
+    pub ms: usize,
+}
+
+impl Default for AutosaveInterval {
+    fn default() -> Self {
+        Self {
+            moves: 5,
+            ms: 30_000,
+        }
+    }
+}
+
+impl AutosaveInterval {
+    pub fn every_move() -> Self {
+        Self {
+            moves: 1,
+            ..Self::default()
+        }
+    }
+
+    // Nothing is due until there's a change that isn't saved yet; an undo
+    // counts as one.
+    pub fn is_due(&self, changes_since_save: u32, ms_since_save: usize) -> bool {
+        changes_since_save > 0 && (changes_since_save >= self.moves || ms_since_save >= self.ms)
+    }
+}
+
+// Enough to rebuild a game in progress by replaying it from the deal.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SavedGame {
+    // Counts up with every save, so the newer of two files wins.
+    pub generation: u32,
+    pub difficulty: Difficulty,
+    pub recording: Recording,
+    pub elapsed_ms: usize,
+}
+
+impl SavedGame {
+    pub fn load() -> Option<Self> {
+        let file_system = FileSystem::get();
+        let read = |path| file_system.read_file_as_string(path).ok();
+        Self::newest(&[read(SAVE_PATH), read(SAVE_TEMP_PATH)])
+    }
+
+    // A write cut short doesn't parse, so it loses to whatever came before.
+    fn newest(files: &[Option<String>]) -> Option<Self> {
+        files
+            .iter()
+            .flatten()
+            .filter_map(|json| serde_json::from_str::<SavedGame>(json).ok())
+            .max_by_key(|saved| saved.generation)
+    }
+
+    // Written beside the last save and renamed over it, so a crash partway
+    // through leaves the previous save as it was.
+    pub fn save(&self) -> Result<(), Error> {
+        let json = serde_json::to_string(self).map_err(Error::msg)?;
+        let file_system = FileSystem::get();
+        let file = file_system.open(SAVE_TEMP_PATH, FileOptions::kFileWrite)?;
+        file.write(json.as_bytes())?;
+        file.flush()?;
+        file_system.rename(SAVE_TEMP_PATH, SAVE_PATH)?;
+        Ok(())
+    }
+
+    // For a game that's over, so the next launch deals a new one.
+    pub fn clear() -> Result<(), Error> {
+        let file_system = FileSystem::get();
+        for path in &[SAVE_PATH, SAVE_TEMP_PATH] {
+            if file_system.read_file_as_string(path).is_ok() {
+                file_system.unlink(path, false)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::klondike::Play;
+    use alloc::vec;
+
+    fn saved(generation: u32) -> SavedGame {
+        SavedGame {
+            generation,
+            difficulty: Difficulty::Vegas,
+            recording: Recording {
+                seed: 324,
+                plays: vec![Play::DrawFromStock, Play::DrawFromStock],
+            },
+            elapsed_ms: 12_000,
+        }
+    }
+
+    #[test]
+    fn test_corrupt_temp_falls_back_to_main() {
+        let main = serde_json::to_string(&saved(3)).expect("to_string");
+        let newer = serde_json::to_string(&saved(4)).expect("to_string");
+        let torn = String::from(&newer[..newer.len() / 2]);
+        assert_eq!(
+            SavedGame::newest(&[Some(main.clone()), Some(torn)]),
+            Some(saved(3))
+        );
+        assert_eq!(
+            SavedGame::newest(&[Some(main), Some(newer)]),
+            Some(saved(4))
+        );
+        assert_eq!(SavedGame::newest(&[None, Some(String::from("{"))]), None);
+    }
+
+    #[test]
+    fn test_autosave_interval() {
+        let interval = AutosaveInterval::default();
+        assert!(!interval.is_due(0, interval.ms * 2));
+        assert!(!interval.is_due(1, 0));
+        assert!(interval.is_due(interval.moves, 0));
+        assert!(interval.is_due(1, interval.ms));
+        assert!(AutosaveInterval::every_move().is_due(1, 0));
+    }
+}
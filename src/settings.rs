@@ -0,0 +1,179 @@
+use enum_iterator::IntoEnumIterator;
+
+#[derive(Clone, Copy, Debug, Eq, IntoEnumIterator, PartialEq)]
+pub enum Setting {
+    HighContrastSuits,
+    ReduceMotion,
+    LeftHanded,
+    DailyDeal,
+    Tutorial,
+    FoundationHints,
+    FoundationsFirst,
+    AutoRecycle,
+    TableauxFirst,
+    StopAtEnds,
+    RevealCards,
+    LargeCards,
+    CardBorders,
+    HighVisibilityCursor,
+    SaveEveryMove,
+}
+
+impl Setting {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Setting::HighContrastSuits => "High-contrast suits",
+            Setting::ReduceMotion => "Reduce motion",
+            Setting::LeftHanded => "Left-handed",
+            Setting::DailyDeal => "Daily deal",
+            Setting::Tutorial => "Tutorial",
+            Setting::FoundationHints => "Foundation hints",
+            Setting::FoundationsFirst => "Foundations first",
+            Setting::AutoRecycle => "Auto-recycle waste",
+            Setting::TableauxFirst => "Tableaux first",
+            Setting::StopAtEnds => "Stop at the ends",
+            Setting::RevealCards => "Reveal cards",
+            Setting::LargeCards => "Large cards",
+            Setting::CardBorders => "Card borders",
+            Setting::HighVisibilityCursor => "High-visibility cursor",
+            Setting::SaveEveryMove => "Save every move",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Settings {
+    pub high_contrast_suits: bool,
+    pub reduce_motion: bool,
+    pub left_handed: bool,
+    pub daily_deal: bool,
+    pub tutorial: bool,
+    pub foundation_hints: bool,
+    pub foundations_first: bool,
+    pub auto_recycle: bool,
+    pub tableaux_first: bool,
+    // Navigation stops at the first and last card instead of wrapping.
+    pub stop_at_ends: bool,
+    // Face-down cards are drawn face up; what can be moved stays the same.
+    pub reveal_cards: bool,
+    pub large_cards: bool,
+    pub card_borders: bool,
+    // The selected card is inverted as well as pointed at.
+    pub high_visibility_cursor: bool,
+    // Autosave after each move rather than every few.
+    pub save_every_move: bool,
+}
+
+impl Settings {
+    pub fn get(&self, setting: Setting) -> bool {
+        match setting {
+            Setting::HighContrastSuits => self.high_contrast_suits,
+            Setting::ReduceMotion => self.reduce_motion,
+            Setting::LeftHanded => self.left_handed,
+            Setting::DailyDeal => self.daily_deal,
+            Setting::Tutorial => self.tutorial,
+            Setting::FoundationHints => self.foundation_hints,
+            Setting::FoundationsFirst => self.foundations_first,
+            Setting::AutoRecycle => self.auto_recycle,
+            Setting::TableauxFirst => self.tableaux_first,
+            Setting::StopAtEnds => self.stop_at_ends,
+            Setting::RevealCards => self.reveal_cards,
+            Setting::LargeCards => self.large_cards,
+            Setting::CardBorders => self.card_borders,
+            Setting::HighVisibilityCursor => self.high_visibility_cursor,
+            Setting::SaveEveryMove => self.save_every_move,
+        }
+    }
+
+    pub fn toggle(&mut self, setting: Setting) {
+        let value = match setting {
+            Setting::HighContrastSuits => &mut self.high_contrast_suits,
+            Setting::ReduceMotion => &mut self.reduce_motion,
+            Setting::LeftHanded => &mut self.left_handed,
+            Setting::DailyDeal => &mut self.daily_deal,
+            Setting::Tutorial => &mut self.tutorial,
+            Setting::FoundationHints => &mut self.foundation_hints,
+            Setting::FoundationsFirst => &mut self.foundations_first,
+            Setting::AutoRecycle => &mut self.auto_recycle,
+            Setting::TableauxFirst => &mut self.tableaux_first,
+            Setting::StopAtEnds => &mut self.stop_at_ends,
+            Setting::RevealCards => &mut self.reveal_cards,
+            Setting::LargeCards => &mut self.large_cards,
+            Setting::CardBorders => &mut self.card_borders,
+            Setting::HighVisibilityCursor => &mut self.high_visibility_cursor,
+            Setting::SaveEveryMove => &mut self.save_every_move,
+        };
+        *value = !*value;
+    }
+}
+
+// How quickly replays and auto-complete step through their moves.
+#[derive(Clone, Copy, Debug, Default, Eq, IntoEnumIterator, PartialEq)]
+pub enum ReplaySpeed {
+    Half,
+    #[default]
+    Normal,
+    Double,
+}
+
+impl ReplaySpeed {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReplaySpeed::Half => "0.5x",
+            ReplaySpeed::Normal => "1x",
+            ReplaySpeed::Double => "2x",
+        }
+    }
+
+    // Frames a step takes at this speed, at least one so none is skipped.
+    pub fn frames(&self, normal: usize) -> usize {
+        match self {
+            ReplaySpeed::Half => normal * 2,
+            ReplaySpeed::Normal => normal,
+            ReplaySpeed::Double => (normal / 2).max(1),
+        }
+    }
+
+    pub fn faster(&self) -> Self {
+        match self {
+            ReplaySpeed::Half => ReplaySpeed::Normal,
+            _ => ReplaySpeed::Double,
+        }
+    }
+
+    pub fn slower(&self) -> Self {
+        match self {
+            ReplaySpeed::Double => ReplaySpeed::Normal,
+            _ => ReplaySpeed::Half,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_replay_speed_scales_frames() {
+        assert_eq!(ReplaySpeed::Half.frames(15), 30);
+        assert_eq!(ReplaySpeed::Normal.frames(15), 15);
+        assert_eq!(ReplaySpeed::Double.frames(15), 7);
+        assert_eq!(ReplaySpeed::Double.frames(1), 1);
+        let speed = ReplaySpeed::default();
+        assert_eq!(speed.faster().faster(), ReplaySpeed::Double);
+        assert_eq!(speed.slower().slower(), ReplaySpeed::Half);
+    }
+
+    #[test]
+    fn test_toggle_changes_only_that_setting() {
+        for setting in Setting::into_enum_iter() {
+            let mut settings = Settings::default();
+            settings.toggle(setting);
+            for other in Setting::into_enum_iter() {
+                assert_eq!(settings.get(other), other == setting, "{:?}", other);
+            }
+            settings.toggle(setting);
+            assert_eq!(settings, Settings::default());
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use anyhow::Error;
+use crankstart::file::{FileOptions, FileSystem};
+use serde::{Deserialize, Serialize};
+
+const STATS_PATH: &str = "stats.json";
+const STATS_TEMP_PATH: &str = "stats.json.tmp";
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Stats {
+    // The most recent day whose daily deal was won.
+    pub daily_completed: Option<usize>,
+    pub last_time_ms: Option<usize>,
+    pub best_time_ms: Option<usize>,
+    // Games given up on for a new deal, and the most cards one of them got
+    // up to the foundations.
+    #[serde(default)]
+    pub abandoned_games: usize,
+    pub furthest_abandoned: Option<usize>,
+    // The fewest cards an abandoned game had left face down.
+    pub fewest_hidden_abandoned: Option<usize>,
+}
+
+impl Stats {
+    pub fn load() -> Self {
+        FileSystem::get()
+            .read_file_as_string(STATS_PATH)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    // Renamed into place like the saved game, so a crash partway through
+    // keeps the old stats.
+    pub fn save(&self) -> Result<(), Error> {
+        let json = serde_json::to_string(self).map_err(Error::msg)?;
+        let file_system = FileSystem::get();
+        let file = file_system.open(STATS_TEMP_PATH, FileOptions::kFileWrite)?;
+        file.write(json.as_bytes())?;
+        file.flush()?;
+        file_system.rename(STATS_TEMP_PATH, STATS_PATH)?;
+        Ok(())
+    }
+
+    pub fn is_daily_completed(&self, day: usize) -> bool {
+        self.daily_completed == Some(day)
+    }
+
+    pub fn complete_daily(&mut self, day: usize) {
+        self.daily_completed = Some(day);
+    }
+
+    pub fn record_abandoned(&mut self, progress: usize, hidden: usize) {
+        self.abandoned_games += 1;
+        self.furthest_abandoned = Some(
+            self.furthest_abandoned
+                .map_or(progress, |furthest| furthest.max(progress)),
+        );
+        self.fewest_hidden_abandoned = Some(
+            self.fewest_hidden_abandoned
+                .map_or(hidden, |fewest| fewest.min(hidden)),
+        );
+    }
+
+    pub fn record_win(&mut self, elapsed_ms: usize) {
+        self.last_time_ms = Some(elapsed_ms);
+        self.best_time_ms = Some(
+            self.best_time_ms
+                .map_or(elapsed_ms, |best| best.min(elapsed_ms)),
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use crate::klondike::{Play, Source, StackId};
+
+pub struct TutorialStep {
+    pub text: &'static str,
+    pub play: Play,
+}
+
+// The steps below only line up with this deal.
+pub const TUTORIAL_SEED: u64 = 322;
+
+pub const TUTORIAL_STEPS: &[TutorialStep] = &[
+    TutorialStep {
+        text: "Aces go up to the foundations first.",
+        play: Play::MoveCards(
+            Source {
+                stack: StackId::Tableau3,
+                index: 2,
+            },
+            StackId::Foundation4,
+        ),
+    },
+    TutorialStep {
+        text: "Build down in alternating colors.",
+        play: Play::MoveCards(
+            Source {
+                stack: StackId::Tableau3,
+                index: 1,
+            },
+            StackId::Tableau2,
+        ),
+    },
+    TutorialStep {
+        text: "A whole run can move at once.",
+        play: Play::MoveCards(
+            Source {
+                stack: StackId::Tableau2,
+                index: 1,
+            },
+            StackId::Tableau3,
+        ),
+    },
+    TutorialStep {
+        text: "Stuck? Deal three from the stock.",
+        play: Play::DrawFromStock,
+    },
+    TutorialStep {
+        text: "Only the top waste card can be played.",
+        play: Play::MoveCards(
+            Source {
+                stack: StackId::Waste,
+                index: 2,
+            },
+            StackId::Tableau3,
+        ),
+    },
+    TutorialStep {
+        text: "That frees the jack underneath it.",
+        play: Play::MoveCards(
+            Source {
+                stack: StackId::Waste,
+                index: 1,
+            },
+            StackId::Tableau1,
+        ),
+    },
+    TutorialStep {
+        text: "Keep building to turn over hidden cards.",
+        play: Play::MoveCards(
+            Source {
+                stack: StackId::Tableau7,
+                index: 6,
+            },
+            StackId::Tableau1,
+        ),
+    },
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::klondike::Table;
+
+    #[test]
+    fn test_tutorial_steps_are_legal() {
+        let mut table = Table::new(TUTORIAL_SEED);
+        for step in TUTORIAL_STEPS {
+            match step.play {
+                Play::MoveCards(source, target) => {
+                    assert!(table.can_move(source, target), "{}", step.text)
+                }
+                Play::DrawFromStock => assert!(table.has_cards_in_stock(), "{}", step.text),
+                _ => panic!("unexpected play {:?}", step.play),
+            }
+            table.apply_play(step.play).expect(step.text);
+        }
+    }
+}